@@ -3,9 +3,9 @@ use bevy::prelude::*;
 //use bevy_inspector_egui::quick::WorldInspectorPlugin;
 
 use crate::model::world::RS98WorldPlugin;
-use crate::program::program::RS98ProgramPlugin;
+use crate::program::{program::RS98ProgramPlugin, remote::RemoteServer};
 
-use super::{input::RS98InputPlugin, camera::RS98CameraPlugin, text::RS98TextPlugin, post::RS98PostPlugin, entities::{RS98EntitiesPlugin}};
+use super::{input::RS98InputPlugin, camera::RS98CameraPlugin, text::RS98TextPlugin, post::RS98PostPlugin, entities::{RS98EntitiesPlugin}, console::RS98ConsolePlugin};
 
 #[derive(StageLabel)]
 pub struct SSCamera;
@@ -46,8 +46,20 @@ pub fn start_app() {
     .add_plugin(RS98InputPlugin)
     .add_plugin(RS98CameraPlugin)
     .add_plugin(RS98TextPlugin)
+    .add_plugin(RS98ConsolePlugin)
     .add_plugin(RS98EntitiesPlugin);
 
+  // --listen 127.0.0.1:9898 lets external tools drive the simulation; --listen-any allows other interfaces
+  let args = std::env::args().collect::<Vec<String>>();
+  if let Some(addr) = args.iter().position(|arg| arg == "--listen").and_then(|ndx| args.get(ndx + 1)) {
+    match RemoteServer::bind(addr, args.iter().any(|arg| arg == "--listen-any")) {
+      Ok(server) => {
+        app.insert_resource(server);
+      }
+      Err(err) => println!("could not listen on {addr}: {err}"),
+    }
+  }
+
   if false { // todo, add configuration
     //app.add_plugin(WorldInspectorPlugin);
     app.add_plugin(RS98PostPlugin);
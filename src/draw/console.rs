@@ -0,0 +1,137 @@
+use bevy::prelude::*;
+
+use crate::{model::auto::AutoNdx, program::program::ProgramSpace};
+
+/// How many lines of scrollback are drawn.
+const CONSOLE_LINES: usize = 16;
+
+pub struct RS98ConsolePlugin;
+
+impl Plugin for RS98ConsolePlugin {
+  fn build(&self, app: &mut App) {
+    app
+      .insert_resource(Console::default())
+      .add_startup_system(setup_console)
+      .add_system(handle_console_input)
+      .add_system(update_console_text.after(handle_console_input));
+  }
+}
+
+#[derive(Resource, Default)]
+pub struct Console {
+  pub open: bool,
+  pub input: String,
+  pub scrollback: Vec<String>,
+  /// Send input to auto 0 (the loader) instead of the accessed auto.
+  pub to_space: bool,
+  /// How far through the program's output the scrollback has got.
+  pub output_cursor: u64,
+}
+
+impl Console {
+  pub fn target(&self, program: &ProgramSpace) -> AutoNdx {
+    if self.to_space {
+      AutoNdx(0)
+    } else {
+      program.access
+    }
+  }
+
+  pub fn submit(&mut self, program: &mut ProgramSpace) {
+    let source = std::mem::take(&mut self.input);
+    if source.trim().is_empty() {
+      return;
+    }
+    let target = self.target(program);
+    self.scrollback.push(format!("{}< {source}", target.0));
+    program.eval(target, &source);
+  }
+}
+
+#[derive(Component)]
+pub struct ConsoleText;
+
+fn setup_console(
+  mut commands: Commands,
+  asset_server: Res<AssetServer>,
+) {
+  let font = asset_server.load("fonts/IBMPlexMono-Regular.ttf");
+  commands.spawn((
+    TextBundle {
+      text: Text::from_section("", TextStyle {
+        font,
+        font_size: 16.0,
+        color: Color::WHITE,
+      }),
+      style: Style {
+        position_type: PositionType::Absolute,
+        position: UiRect {
+          left: Val::Px(10.0),
+          bottom: Val::Px(10.0),
+          ..default()
+        },
+        ..default()
+      },
+      background_color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+      visibility: Visibility { is_visible: false },
+      ..default()
+    },
+    ConsoleText,
+  ));
+}
+
+pub fn handle_console_input(
+  keys: Res<Input<KeyCode>>,
+  mut chars: EventReader<ReceivedCharacter>,
+  mut console: ResMut<Console>,
+  mut program: ResMut<ProgramSpace>,
+) {
+  if keys.just_pressed(KeyCode::Grave) {
+    console.open = !console.open;
+    chars.clear();
+    return;
+  }
+  if !console.open {
+    chars.clear();
+    return;
+  }
+
+  for ev in chars.iter() {
+    if !ev.char.is_control() && ev.char != '`' {
+      console.input.push(ev.char);
+    }
+  }
+  if keys.just_pressed(KeyCode::Back) {
+    console.input.pop();
+  }
+  if keys.just_pressed(KeyCode::Tab) {
+    console.to_space = !console.to_space;
+  }
+  if keys.just_pressed(KeyCode::Return) {
+    console.submit(&mut program);
+  }
+}
+
+fn update_console_text(
+  mut q_text: Query<(&mut Text, &mut Visibility), With<ConsoleText>>,
+  mut console: ResMut<Console>,
+  program: Res<ProgramSpace>,
+) {
+  // print output and return values come back through the program's output
+  let output = program.output_since(&mut console.output_cursor);
+  console.scrollback.extend(output);
+  let len = console.scrollback.len();
+  if len > CONSOLE_LINES {
+    console.scrollback.drain(0..len - CONSOLE_LINES);
+  }
+
+  for (mut text, mut visibility) in q_text.iter_mut() {
+    visibility.is_visible = console.open;
+    if console.open {
+      let target = console.target(&program);
+      let mut lines = console.scrollback.clone();
+      lines.push(format!("{}> {}_", target.0, console.input));
+      text.sections[0].value = lines.join("\n");
+    }
+  }
+}
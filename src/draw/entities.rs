@@ -2,7 +2,7 @@ use std::{collections::{HashMap, HashSet}, f32::consts::PI};
 
 use bevy::{prelude::*, scene::SceneBundle};
 
-use crate::{model::{world::World, auto::AutoNdx, kind::Kind}, program::program::ProgramSpace};
+use crate::{model::{world::World, auto::AutoNdx, kind::Kind, dir::Dir}, program::program::ProgramSpace};
 
 #[derive(Component, PartialEq, Eq, Hash, Clone, Copy, Debug)]
 pub enum TrackedEntity {
@@ -19,12 +19,14 @@ pub struct Physics {
 #[derive(Component)]
 pub struct EntityKind {
   pub kind: Kind,
+  pub scene: String,
 }
 
 #[allow(clippy::too_many_arguments)]
 pub fn update_entity(
   tracker: TrackedEntity,
   loc: Vec3,
+  rotation: Quat,
   kind: Kind,
   commands: &mut Commands,
   world: &Res<World>,
@@ -51,8 +53,8 @@ pub fn update_entity(
 
     } else if let Ok((old_kind, mut transform, mut physics)) = q.get_mut(entity) {
 
-      if old_kind.kind != kind {
-        // kill it and it'll respawn next frame with the right kind
+      if old_kind.kind != kind || old_kind.scene != world.kinds.get_data(kind).scene {
+        // kill it and it'll respawn next frame with the right kind and scene
         commands.entity(entity).despawn_recursive();
         entities.entities_map.remove(&tracker);
         return;
@@ -86,9 +88,9 @@ pub fn update_entity(
               vel
             };
             transform.translation += vel;
-            transform.rotation = rot_z;
           }
         }
+        transform.rotation = rotation;
 
       } else {
         transform.translation = loc;
@@ -98,7 +100,7 @@ pub fn update_entity(
   } else if kind != Kind(0) {
     let data = world.kinds.get_data(kind);
     let scene = ass.load(data.scene.clone());
-    let transform = Transform::from_translation(loc);
+    let transform = Transform::from_translation(loc).with_rotation(rotation);
     //transform.rotate(Quat::from_rotation_x(PI/2.0));
     let entity = commands.spawn((
       SceneBundle {
@@ -112,6 +114,7 @@ pub fn update_entity(
       },
       EntityKind {
         kind,
+        scene: data.scene.clone(),
       },
     )).id();
 
@@ -123,6 +126,16 @@ pub fn update_entity(
   }
 }
 
+/// Models face north, and east is a quarter turn clockwise.
+fn facing_rotation(facing: Dir) -> Quat {
+  match facing {
+    Dir::East => Quat::from_rotation_z(-PI / 2.0),
+    Dir::South => Quat::from_rotation_z(PI),
+    Dir::West => Quat::from_rotation_z(PI / 2.0),
+    Dir::North | Dir::None => Quat::IDENTITY,
+  }
+}
+
 fn mix(vel: Vec3, diff: Vec3, arg: f32) -> Vec3 {
   let diff = diff * arg;
   let vel = vel * (1.0 - arg);
@@ -147,7 +160,13 @@ pub fn update_entities(
   //to_update.insert(TrackedEntity::Auto(parent_ndx));
   // println!("access items: {:?} {:?}", access, world.get_auto(access).items);
 
+  // only draw what the accessed auto's force knows about
+  let force = world.get_auto(access).force;
+
   for auto_ndx in parent.children.iter() {
+    if !world.auto_visible(force, *auto_ndx) {
+      continue;
+    }
     to_update.insert(TrackedEntity::Auto(*auto_ndx));
     let auto = world.get_auto(*auto_ndx);
     for i in 0..auto.items.len() {
@@ -159,7 +178,7 @@ pub fn update_entities(
   }
 
   for (i, item) in parent.items.iter().enumerate() {
-    if *item != Kind(0) {
+    if *item != Kind(0) && world.cell_seen(force, parent_ndx, parent.ndx_to_loc(i)) {
       to_update.insert(TrackedEntity::Item(parent_ndx, i));
     }
   }
@@ -168,9 +187,12 @@ pub fn update_entities(
   let num_tiles_to_update = num_tiles.min(20);
   for _ in 0..num_tiles_to_update {
     let loc = entities.tile_update;
+    entities.tile_update = (entities.tile_update + 1) % num_tiles;
+    if !world.cell_seen(force, parent_ndx, parent.ndx_to_loc(loc)) {
+      continue;
+    }
     to_update.insert(TrackedEntity::Tile(parent_ndx, loc));
     to_update.insert(TrackedEntity::Item(parent_ndx, loc));
-    entities.tile_update = (entities.tile_update + 1) % num_tiles;
   }
 
   let existing = entities.entities_map.keys().cloned().collect::<HashSet<_>>();
@@ -197,26 +219,34 @@ pub fn update_entities(
     match tracker {
       TrackedEntity::Auto(auto_ndx) => {
         let auto = world.get_auto(auto_ndx);
-        let auto_loc = auto.loc.as_vec2().extend(0.0);
+        // the model turns about its first cell, so shift it back over its footprint
+        let turned_origin = auto.ndx_to_loc(0).as_vec2().extend(0.0);
+        let auto_loc = auto.loc.as_vec2().extend(0.0) + turned_origin;
         //println!("auto: {:?} {:?} {:?}/{:?}", auto.kind, auto_loc, auto_ndx, access);
-        update_entity(tracker, auto_loc, auto.kind, &mut commands, &world, &mut entities, &ass, &mut q, &time);
+        update_entity(tracker, auto_loc, facing_rotation(auto.facing), auto.kind, &mut commands, &world, &mut entities, &ass, &mut q, &time);
       },
       TrackedEntity::Tile(auto_ndx, loc) => {
         let auto = world.get_auto(auto_ndx);
         let auto_loc = auto.loc.as_vec2().extend(0.0);
         let tile = auto.tiles[loc];
         let loc = auto_loc + auto.ndx_to_loc(loc).as_vec2().extend(0.0);
-        update_entity(tracker, loc, tile, &mut commands, &world, &mut entities, &ass, &mut q, &time);
+        update_entity(tracker, loc, Quat::IDENTITY, tile, &mut commands, &world, &mut entities, &ass, &mut q, &time);
       },
       TrackedEntity::Item(auto_ndx, loc) => {
         let auto = world.get_auto(auto_ndx);
         let item = auto.items[loc];
-        let loc = auto.ndx_to_loc(loc).as_vec2().extend(0.0);
-        let loc = loc + Vec3::new(0.0, 0.0, 0.0);
+        let cell = auto.ndx_to_loc(loc);
+        // items on a conveyor slide over from the cell they came from
+        let loc = match world.conveyed_from(auto_ndx, cell) {
+          Some(from) => from.as_vec2().lerp(cell.as_vec2(), world.conveyor_time.min(1.0) as f32),
+          // held items turn along with the auto they're parented to
+          None => auto.unrotated_loc(loc).as_vec2(),
+        };
+        let loc = loc.extend(0.0);
         // if item != Kind(0) {
         //   println!("item: {:?} {:?} {:?}/{:?}", item, loc, auto_ndx, access);
         // }
-        update_entity(tracker, loc, item, &mut commands, &world, &mut entities, &ass, &mut q, &time);
+        update_entity(tracker, loc, Quat::IDENTITY, item, &mut commands, &world, &mut entities, &ass, &mut q, &time);
       },
     }
   }
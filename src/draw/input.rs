@@ -3,7 +3,7 @@ use conniver::p;
 
 use crate::{program::program::ProgramSpace};
 
-use super::camera::CameraTarget;
+use super::{camera::CameraTarget, console::Console};
 
 pub struct RS98InputPlugin;
 
@@ -81,7 +81,12 @@ pub fn handle_mouse_input(
 pub fn handle_keyboard_input(
   keys: Res<Input<KeyCode>>,
   mut program: ResMut<ProgramSpace>,
+  console: Option<Res<Console>>,
 ) {
+  // keys typed into the console don't drive the robot
+  if console.map_or(false, |console| console.open) {
+    return;
+  }
   for key in keys.get_pressed() {
     let event = p(&format!("(input-key {key:?})"));
     let access = program.access;
@@ -1,5 +1,6 @@
 pub mod app;
 pub mod camera;
+pub mod console;
 pub mod entities;
 pub mod input;
 pub mod post;
@@ -1,6 +1,8 @@
 use bevy::prelude::*;
 
-use crate::{model::{world::World, auto::{AutoNdx}}, draw::entities::{update_entities, TrackedEntity}, program::program::ProgramSpace};
+use conniver::{Val, object::read_string};
+
+use crate::{model::{world::World, auto::{AutoNdx, Auto}}, draw::{console::Console, entities::{update_entities, TrackedEntity}}, program::{program::ProgramSpace, test::run1}};
 
 use super::entities::Entities;
 
@@ -46,3 +48,75 @@ fn test_entities() {
   let new_scene = app.world.get::<Handle<Scene>>(entity).unwrap().clone();
   assert_ne!(scene, new_scene);
 }
+
+#[test]
+fn test_entities_scene_reload() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  let loc = IVec2::new(10, 10);
+  let loc_ndx = world.get_auto(space).get_ndx(loc);
+  world.set_item(space, loc, world.kinds.get("rock"));
+
+  let mut app = App::new();
+  app.add_plugin(CorePlugin::default());
+  app.add_plugin(AssetPlugin::default());
+  app.insert_resource(world);
+  app.insert_resource(Entities::new());
+  app.insert_resource(ProgramSpace::new(space));
+  app.insert_resource(Time::default());
+  app.add_system(update_entities);
+
+  app.update();
+
+  let tracker = TrackedEntity::Item(space, loc_ndx as usize);
+  let entities = app.world.resource::<Entities>();
+  let entity = *entities.entities_map.get(&tracker).unwrap();
+  let scene = app.world.get::<Handle<Scene>>(entity).unwrap().clone();
+
+  // same kind, new scene, as if kinds.cnvr had been edited
+  let mut world = app.world.resource_mut::<World>();
+  world.kinds.set_by_val("rock", conniver::p("((scene \"model/ore.glb#Scene0\"))"));
+
+  app.update();
+  app.update();
+
+  let entities = app.world.resource::<Entities>();
+  let entity = *entities.entities_map.get(&tracker).unwrap();
+  let new_scene = app.world.get::<Handle<Scene>>(entity).unwrap().clone();
+  assert_ne!(scene, new_scene);
+}
+
+#[test]
+fn test_console_debug() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  let robo = |world: &mut World, force| world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(10, 10),
+    parent: space,
+    force,
+    ..Auto::default()
+  });
+  let force = world.get_auto(space).force;
+  let red = world.forces.define("red");
+  let player = robo(&mut world, force);
+  let worker = robo(&mut world, force);
+  let stranger = robo(&mut world, red);
+
+  // the console's default target is the accessed robot, which may debug its own force's autos only
+  let mut program = ProgramSpace::new(player);
+  program.ensure_size(stranger.0);
+  let mut console = Console::default();
+  for target in [worker, stranger] {
+    console.input = format!("(debug {} 'where)", target.0);
+    console.submit(&mut program);
+    for _ in 0..5 {
+      run1(&mut world, &mut program, 1.0);
+    }
+  }
+  let running = read_string(&Val::String("running, idle".to_string()));
+  assert!(program.output.contains(&format!("{}> {running}", player.0)));
+  let refused = read_string(&Val::String(format!("error: auto {} is not ours", stranger.0)));
+  assert!(program.output.contains(&format!("{}> {refused}", player.0)));
+}
@@ -1,44 +1,69 @@
 use bevy::prelude::*;
 
+use crate::{model::world::World, program::program::ProgramSpace};
+
 pub struct RS98TextPlugin;
 
 impl Plugin for RS98TextPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(setup_text);
+        app.add_startup_system(setup_text)
+            .add_system(update_status_text);
     }
 }
 
+/// Shows what is wrong with the accessed auto, if anything.
+#[derive(Component)]
+pub struct StatusText;
+
 fn setup_text(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
 ) {
     let font = asset_server.load("fonts/IBMPlexMono-Regular.ttf");
-    commands.spawn(TextBundle {
-        text: Text {
-            sections: vec![
-                TextSection {
-                    value: "Hello ".to_string(),
-                    style: TextStyle {
-                        font: font.clone(),
-                        font_size: 40.0,
-                        color: Color::WHITE,
+    commands.spawn((
+        TextBundle {
+            text: Text {
+                sections: vec![
+                    TextSection {
+                        value: "".to_string(),
+                        style: TextStyle {
+                            font: font.clone(),
+                            font_size: 20.0,
+                            color: Color::WHITE,
+                        },
                     },
-                },
-                TextSection {
-                    value: "World!".to_string(),
-                    style: TextStyle {
-                        font,
-                        font_size: 40.0,
-                        color: Color::RED,
+                    TextSection {
+                        value: "".to_string(),
+                        style: TextStyle {
+                            font,
+                            font_size: 20.0,
+                            color: Color::RED,
+                        },
                     },
-                },
-            ],
-            alignment: Default::default(),
-        },
-        style: Style {
-            align_self: AlignSelf::FlexStart,
+                ],
+                alignment: Default::default(),
+            },
+            style: Style {
+                align_self: AlignSelf::FlexStart,
+                ..Default::default()
+            },
             ..Default::default()
         },
-        ..Default::default()
-    });
-}
\ No newline at end of file
+        StatusText,
+    ));
+}
+
+fn update_status_text(
+    mut q_text: Query<&mut Text, With<StatusText>>,
+    world: Res<World>,
+    program: Res<ProgramSpace>,
+) {
+    let access = program.access;
+    let stall = world.get_auto(access).stall_message.clone().unwrap_or_default();
+    let behaviour = program.behaviour_status(access).map(|status| format!("{status}\n")).unwrap_or_default();
+    let error = program.last_error(access).map(|error| error.describe()).unwrap_or_default();
+    for mut text in q_text.iter_mut() {
+        text.sections[0].value = format!("{behaviour}{stall}\n");
+        text.sections[1].value = error.clone();
+    }
+}
@@ -0,0 +1,5 @@
+pub mod draw;
+pub mod model;
+pub mod program;
+
+pub use program::registry::{MessageRegistry, ScriptAppExt};
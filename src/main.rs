@@ -1,7 +1,9 @@
-mod draw;
-mod model;
-mod program;
+use rs98_world_model::{draw, program, MessageRegistry};
 
 fn main() {
-  draw::app::start_app();
+  if std::env::args().any(|arg| arg == "--headless") {
+    program::repl::run_repl(&MessageRegistry::default());
+  } else {
+    draw::app::start_app();
+  }
 }
@@ -2,7 +2,11 @@ use bevy::prelude::IVec2;
 
 use crate::model::{kind::{Kind, KindRole}, world::World, auto::{AutoNdx, Auto}, dir::Dir, route::route, slot::Slot};
 
-use super::auto::auto_alive;
+use super::{auto::auto_alive, force::Relation, event::WorldEvent};
+
+/// What a custom action does each time it's attempted: like `Action::act`, it calls
+/// `finish_auto_action` when done, or returns a stall message.
+pub type CustomAction = fn(&mut World, AutoNdx) -> Option<String>;
 
 #[allow(dead_code)]
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
@@ -14,13 +18,19 @@ pub enum Action {
   Place(Kind),
   Produce,
   Fire(AutoNdx),
+  /// Moves one item from the cell behind the auto to the cell in front of it.
+  Transfer,
+  /// Turns the auto to face a direction, turning its footprint with it.
+  Rotate(Dir),
+  /// An index into the world's custom actions.
+  Custom(usize),
 }
 
 impl Action {
   pub fn act(&self, world: &mut World, auto_ndx: AutoNdx) -> Option<String> {
     match self {
       Action::Stop => {
-        world.finish_auto_action(auto_ndx);
+        world.idle_auto(auto_ndx);
         None
       }
 
@@ -32,6 +42,10 @@ impl Action {
         if world.traction_valid(parent, kind, new_loc) {
           let auto = world.get_auto_mut(auto_ndx);
           auto.loc = new_loc;
+          // turning anything bigger would swing its footprint round without checking where it lands
+          if auto.dim == IVec2::ONE {
+            auto.facing = *dir;
+          }
           world.finish_auto_action(auto_ndx);
           None
         } else {
@@ -49,8 +63,18 @@ impl Action {
         let target = world.pick_place_target(auto_ndx, *source, *item);
         if let Some(Slot(target_auto, target_ndx)) = target {
           let contents = world.get_item(target_auto, target_ndx);
-          world.set_item(target_auto, target_ndx, world.kinds.nothing());
-          world.set_item(auto_ndx, IVec2::new(0, 0), contents);
+          let hand = IVec2::new(0, 0);
+          let room = world.stack_room(auto_ndx, hand, contents);
+          if room <= 0 {
+            return Some(format!("No room in hand for {}.", world.kinds.name(contents)));
+          }
+
+          // take as much of the stack as fits in the hand
+          let available = world.get_count(target_auto, target_ndx);
+          let moved = available.min(room);
+          let holding = world.get_count(auto_ndx, hand);
+          world.set_stack(target_auto, target_ndx, contents, available - moved);
+          world.set_stack(auto_ndx, hand, contents, holding + moved);
           world.finish_auto_action(auto_ndx);
           let target_kind = world.get_auto(target_auto).kind;
           let target_name = if target_auto == world.get_auto(auto_ndx).parent {
@@ -70,16 +94,23 @@ impl Action {
         if holding_kind == Kind(0) {
           return Some("Cannot place nothing.".to_string());
         }
-        let target = world.pick_place_target(auto_ndx, *dest, Kind(0));
+        // autos never stack, since each one placed on the ground becomes its own auto
+        let is_auto = world.kinds.get_data(holding_kind).role == KindRole::Auto;
+        let target = if is_auto {
+          world.pick_place_target(auto_ndx, *dest, Kind(0))
+        } else {
+          world.place_target(auto_ndx, *dest, holding_kind)
+        };
         if let Some(Slot(target_auto, target_ndx)) = target {
 
           // if we're placing an auto on the ground, create it; otherwise, just place the item
-          let is_auto = world.kinds.get_data(holding_kind).role == KindRole::Auto;
           let me = world.get_auto(auto_ndx);
           let parent = me.parent;
           let force = me.force;
+          let hand = IVec2::new(0, 0);
+          let holding = world.get_count(auto_ndx, hand);
           if is_auto && target_auto == parent {
-            world.set_item(auto_ndx, IVec2::new(0, 0), Kind(0));
+            world.set_stack(auto_ndx, hand, holding_kind, holding - 1);
             world.create_auto(Auto {
               kind: holding_kind,
               loc: target_ndx,
@@ -88,8 +119,13 @@ impl Action {
               ..Default::default()
             });
           } else {
-            world.set_item(auto_ndx, IVec2::new(0, 0), Kind(0));
-            world.set_item(target_auto, target_ndx, holding_kind);
+            let there = world.get_count(target_auto, target_ndx);
+            let moved = holding.min(world.stack_room(target_auto, target_ndx, holding_kind));
+            world.set_stack(auto_ndx, hand, holding_kind, holding - moved);
+            world.set_stack(target_auto, target_ndx, holding_kind, there + moved);
+            if target_auto != parent {
+              world.events.push(WorldEvent::ItemArrived(target_auto, target_ndx, holding_kind));
+            }
           }
 
           world.finish_auto_action(auto_ndx);
@@ -208,12 +244,19 @@ impl Action {
       Action::Produce => {
         let auto_data = world.get_auto(auto_ndx);
         let holding = world.get_items(auto_ndx);
-        let pattern = world.get_pattern(auto_data.kind, &holding);
+        let counts = world.get_counts(auto_ndx);
+        let pattern = world.get_pattern(auto_data.kind, &holding, &counts);
         if let Some(pattern) = pattern {
-          for (ndx, kind) in pattern.output.iter().enumerate() {
+          // the inputs stay put rather than feeding output that has nowhere to go
+          let results = pattern.apply(&holding, &counts);
+          if let Some((kind, count)) = results.iter().find(|(kind, count)| *count > world.kinds.get_data(*kind).max_stack()) {
+            return Some(format!("No room for {count} {}.", world.kinds.name(*kind)));
+          }
+          for (ndx, (kind, count)) in results.into_iter().enumerate() {
             let loc = world.get_auto(auto_ndx).ndx_to_loc(ndx);
-            world.set_item(auto_ndx, loc, *kind);
+            world.set_stack(auto_ndx, loc, kind, count);
           }
+          world.events.push(WorldEvent::Produced(auto_ndx));
           world.finish_auto_action(auto_ndx);
           None
         } else {
@@ -222,18 +265,96 @@ impl Action {
       }
 
       Action::Fire(other) => {
+        if world.relation(auto_ndx, *other) != Relation::Hostile {
+          return Some("Target is not hostile.".to_string());
+        }
         let my_loc = world.get_auto(auto_ndx).loc;
         let other_loc = world.get_auto(*other).loc;
         let dist = my_loc - other_loc;
         let dist = dist.x.abs() + dist.y.abs();
         if dist <= 5 {
           world.get_auto_mut(*other).flags.set(auto_alive, false);
+          world.events.push(WorldEvent::AutoDestroyed(*other));
+          world.tasks.release_auto(*other);
           world.finish_auto_action(auto_ndx);
           None
         } else {
           Some("Target out of range.".to_string())
         }
       }
+
+      Action::Transfer => {
+        let auto = world.get_auto(auto_ndx);
+        let (parent, loc, facing, filter) = (auto.parent, auto.loc, auto.facing.to_ivec2(), auto.filter.clone());
+        let name = world.kinds.name(auto.kind);
+        let source = world.reach_slots(auto_ndx, loc - facing).into_iter().find(|slot| {
+          let item = world.get_item(slot.0, slot.1);
+          item != Kind(0) && (filter.is_empty() || filter.contains(&item))
+        });
+        let Slot(source_auto, source_loc) = match source {
+          Some(source) => source,
+          None => return Some(format!("Nothing for {name} to take.")),
+        };
+        let item = world.get_item(source_auto, source_loc);
+        let dest = world.reach_slots(auto_ndx, loc + facing).into_iter()
+          .find(|slot| world.stack_room(slot.0, slot.1, item) > 0);
+        let Slot(dest_auto, dest_loc) = match dest {
+          Some(dest) => dest,
+          None => return Some(format!("No room for {} in front of {name}.", world.kinds.name(item))),
+        };
+
+        let available = world.get_count(source_auto, source_loc);
+        let there = world.get_count(dest_auto, dest_loc);
+        world.set_stack(source_auto, source_loc, item, available - 1);
+        world.set_stack(dest_auto, dest_loc, item, there + 1);
+        if dest_auto != parent {
+          world.events.push(WorldEvent::ItemArrived(dest_auto, dest_loc, item));
+        }
+        world.finish_auto_action(auto_ndx);
+        None
+      }
+
+      Action::Rotate(dir) => {
+        if *dir == Dir::None {
+          return Some("Cannot face nowhere.".to_string());
+        }
+        let auto = world.get_auto(auto_ndx);
+        let (parent, loc) = (auto.parent, auto.loc);
+        let (old, new) = (auto.footprint(), auto.footprint_facing(*dir));
+        // machines don't move under their own traction, so they may turn onto any ground as easy as their own
+        let footing = world.kinds.get_data(world.get_tile(parent, loc)).traction;
+
+        // only the cells it turns onto need checking; it already stands on the rest
+        for y in 0..new.y {
+          for x in 0..new.x {
+            if x < old.x && y < old.y {
+              continue;
+            }
+            let cell = loc + IVec2::new(x, y);
+            if !world.in_bounds(parent, cell) {
+              return Some(format!("Could not turn: ({},{}) is off the edge.", cell.x, cell.y));
+            }
+            let tile = world.get_tile(parent, cell);
+            if world.kinds.get_data(tile).traction > footing {
+              let tile_name = world.kinds.name(tile);
+              return Some(format!("Could not turn: {tile_name} is in the way at ({},{}).", cell.x, cell.y));
+            }
+            if let Some(Slot(other, _)) = world.get_slots(parent, cell).into_iter().find(|slot| slot.0 != auto_ndx) {
+              let other_name = world.kinds.name(world.get_auto(other).kind);
+              return Some(format!("Could not turn: {other_name} is in the way at ({},{}).", cell.x, cell.y));
+            }
+          }
+        }
+
+        world.get_auto_mut(auto_ndx).facing = *dir;
+        world.finish_auto_action(auto_ndx);
+        None
+      }
+
+      Action::Custom(ndx) => {
+        let behaviour = world.custom_actions[*ndx].1;
+        behaviour(world, auto_ndx)
+      }
     }
   }
 }
@@ -5,7 +5,7 @@ use conniver::{read_object, read_ivec2, object::read_string, Val};
 
 use crate::model::{kind::Kind, act::Action};
 
-use super::{kind::Kinds, force::ForceNdx, world::World, bitfield::{BitField, BFNdx}};
+use super::{dir::Dir, kind::Kinds, force::ForceNdx, world::World, bitfield::{BitField, BFNdx}};
 
 #[derive(Clone, Default, Debug)]
 pub struct Auto {
@@ -14,6 +14,7 @@ pub struct Auto {
   pub children: Vec<AutoNdx>,
   pub force: ForceNdx,
   pub items: Vec<Kind>,
+  pub counts: Vec<i32>,
   pub tiles: Vec<Kind>,
   pub dim: IVec2,
   pub action: Action,
@@ -21,6 +22,12 @@ pub struct Auto {
   pub action_time: f64,
   pub stall_message: Option<String>,
   pub flags: BitField,
+  /// Runs instead of the kind's program when the auto's proc restarts, unless nil.
+  pub program: Val,
+  /// Which way it faces. An inserter takes from the cell behind it and puts into the cell in front.
+  pub facing: Dir,
+  /// The kinds an inserter moves, or any if empty.
+  pub filter: Vec<Kind>,
 }
 
 #[allow(dead_code)]
@@ -48,17 +55,71 @@ impl Auto {
     }
   }
 
+  pub fn get_count(&self, loc: IVec2) -> i32 {
+    let ndx = self.get_ndx(loc);
+    if ndx >= 0 && ndx < self.counts.len() as i32 {
+      self.counts[ndx as usize]
+    } else {
+      0
+    }
+  }
+
   pub fn set_item(&mut self, loc: IVec2, item: Kind) {
+    let count = if item == Kind(0) { 0 } else { 1 };
+    self.set_stack(loc, item, count);
+  }
+
+  pub fn set_stack(&mut self, loc: IVec2, item: Kind, count: i32) {
     let ndx = self.get_ndx(loc);
     if ndx >= 0 && ndx < self.items.len() as i32 {
+      // an empty stack is always nothing, and nothing is always an empty stack
+      let (item, count) = if item == Kind(0) || count <= 0 { (Kind(0), 0) } else { (item, count) };
       self.items[ndx as usize] = item;
+      self.counts[ndx as usize] = count;
     }
   }
 
+  /// Indexes its items and tiles by a cell relative to its loc, as laid out on its parent.
   pub fn get_ndx(&self, loc: IVec2) -> i32 {
+    let loc = self.unrotate(loc);
     loc.x + loc.y * self.dim.x
   }
 
+  /// Its dim as laid out on its parent: turned on its side when facing east or west.
+  pub fn footprint(&self) -> IVec2 {
+    self.footprint_facing(self.facing)
+  }
+
+  /// The footprint it would have if it faced this way.
+  pub fn footprint_facing(&self, facing: Dir) -> IVec2 {
+    match facing {
+      Dir::East | Dir::West => IVec2::new(self.dim.y, self.dim.x),
+      _ => self.dim,
+    }
+  }
+
+  /// Turns a cell of its footprint into the cell it would be if the auto faced north. Facing east is a
+  /// quarter turn clockwise, and the footprint always starts at the auto's loc.
+  fn unrotate(&self, loc: IVec2) -> IVec2 {
+    let dim = self.dim;
+    match self.facing {
+      Dir::East => IVec2::new(dim.x - 1 - loc.y, loc.x),
+      Dir::South => IVec2::new(dim.x - 1 - loc.x, dim.y - 1 - loc.y),
+      Dir::West => IVec2::new(loc.y, dim.y - 1 - loc.x),
+      Dir::North | Dir::None => loc,
+    }
+  }
+
+  fn rotate(&self, loc: IVec2) -> IVec2 {
+    let dim = self.dim;
+    match self.facing {
+      Dir::East => IVec2::new(loc.y, dim.x - 1 - loc.x),
+      Dir::South => IVec2::new(dim.x - 1 - loc.x, dim.y - 1 - loc.y),
+      Dir::West => IVec2::new(dim.y - 1 - loc.y, loc.x),
+      Dir::North | Dir::None => loc,
+    }
+  }
+
   pub fn initalize(&self, kinds: &Kinds) -> Auto {
     let mut new = self.clone();
     let kind_data = kinds.get_data(new.kind);
@@ -70,6 +131,15 @@ impl Auto {
     if new.items.len() < num_items {
       new.items.resize(num_items, Kind(0));
     }
+    if new.counts.len() < new.items.len() {
+      new.counts.resize(new.items.len(), 0);
+    }
+    // items given without counts are stacks of one
+    for (item, count) in new.items.iter().zip(new.counts.iter_mut()) {
+      if *item != Kind(0) && *count <= 0 {
+        *count = 1;
+      }
+    }
     if new.tiles.len() < num_items {
       new.tiles.resize(num_items, Kind(0));
     }
@@ -89,6 +159,15 @@ impl Auto {
         },
         "force" => auto.force = world.forces.get(&read_string(val)),
         "tile" => tile_kind = world.kinds.get(&read_string(val)),
+        "program" => auto.program = val.clone(),
+        "facing" => match Dir::from_str(&read_string(val)) {
+          Dir::None => println!("bad facing: {val:?}"),
+          dir => auto.facing = dir,
+        },
+        "filter" => match read_filter(val, &world.kinds) {
+          Ok(filter) => auto.filter = filter,
+          Err(name) => println!("bad filter kind: {name}"),
+        },
         "dim" => read_ivec2(val, |x, y| {
           auto.dim = IVec2::new(x, y);
         }, || {
@@ -109,10 +188,32 @@ impl Auto {
     auto
   }
 
+  /// The inverse of `get_ndx`.
   pub fn ndx_to_loc(&self, ndx: usize) -> IVec2 {
+    self.rotate(self.unrotated_loc(ndx))
+  }
+
+  /// Where an item sits in the auto's own model, before it's turned to face its way.
+  pub fn unrotated_loc(&self, ndx: usize) -> IVec2 {
     IVec2::new(ndx as i32 % self.dim.x, ndx as i32 / self.dim.x)
   }
 }
 
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub struct AutoNdx(pub usize);
+
+/// Reads an inserter's filter, one kind or a list of them, failing with the name of any kind that doesn't exist.
+pub fn read_filter(val: &Val, kinds: &Kinds) -> Result<Vec<Kind>, String> {
+  let names = match val {
+    Val::List(names) => names.iter().map(read_string).collect(),
+    _ => vec![read_string(val)],
+  };
+  names.into_iter().map(|name| {
+    let kind = kinds.get(&name);
+    if kind == kinds.missingno() {
+      Err(name)
+    } else {
+      Ok(kind)
+    }
+  }).collect()
+}
@@ -0,0 +1,174 @@
+use std::collections::HashSet;
+
+use bevy::prelude::IVec2;
+
+use super::{auto::AutoNdx, event::WorldEvent, kind::{Kind, KindRole}, world::World};
+
+#[cfg(test)]
+use super::auto::Auto;
+
+impl World {
+  /// Advances items on conveyor tiles one cell each time a tick's worth of time has passed.
+  pub fn update_conveyors(&mut self, dur: f64) {
+    self.conveyor_time += dur;
+    if self.conveyor_time < 1.0 {
+      return;
+    }
+    self.conveyor_time = 0.0;
+    self.conveyed.clear();
+    for parent in self.auto_ndxes() {
+      self.convey(parent);
+    }
+  }
+
+  /// Moves every item on a conveyor of this auto's ground one cell along, onto an empty cell, or feeds one
+  /// of them into the slot of a machine the belt runs into, if the belt's owner may access it. Items wait
+  /// behind anything else, including robots, but a line of items moves together.
+  fn convey(&mut self, parent: AutoNdx) {
+    let auto = self.get_auto(parent);
+    let belts = auto.tiles.iter().enumerate()
+      .filter(|(ndx, tile)| self.kinds.get_data(**tile).role == KindRole::Conveyor && auto.items.get(*ndx).is_some_and(|item| *item != Kind(0)))
+      .map(|(ndx, tile)| (auto.ndx_to_loc(ndx), self.kinds.get_data(*tile).direction))
+      .collect::<Vec<_>>();
+    if belts.is_empty() {
+      return;
+    }
+
+    // items that have already moved this tick
+    let mut moved = HashSet::new();
+    let mut progress = true;
+    while progress {
+      progress = false;
+      for (loc, dir) in belts.iter().copied() {
+        let item = self.get_item(parent, loc);
+        if item == Kind(0) || moved.contains(&loc) {
+          continue;
+        }
+        let next = loc + dir.to_ivec2();
+        let count = self.get_count(parent, loc);
+        let slots = self.get_slots(parent, next);
+        let target = slots.iter().find(|slot| {
+          !self.is_mobile(slot.0) && self.may_access(parent, slot.0) && self.stack_room(slot.0, slot.1, item) > 0
+        });
+        if let Some(slot) = target {
+          let there = self.get_count(slot.0, slot.1);
+          self.set_stack(slot.0, slot.1, item, there + 1);
+          self.set_stack(parent, loc, item, count - 1);
+          self.events.push(WorldEvent::ItemArrived(slot.0, slot.1, item));
+          moved.insert(loc);
+          progress = true;
+        } else if slots.is_empty() && self.in_bounds(parent, next) && self.get_item(parent, next) == Kind(0)
+          && self.passable(parent, loc, next) {
+          self.set_stack(parent, loc, Kind(0), 0);
+          self.set_stack(parent, next, item, count);
+          self.conveyed.push((parent, loc, next));
+          moved.insert(next);
+          progress = true;
+        }
+      }
+    }
+  }
+
+  /// Whether a belt can push an item onto the next cell: walls and the like, harder to cross than the
+  /// belt itself, stop it.
+  fn passable(&self, parent: AutoNdx, belt: IVec2, next: IVec2) -> bool {
+    let traction = |loc| self.kinds.get_data(self.get_tile(parent, loc)).traction;
+    traction(next) <= traction(belt)
+  }
+
+  /// Where the item now at this cell was before the last conveyor tick, if it was carried there.
+  pub fn conveyed_from(&self, parent: AutoNdx, loc: IVec2) -> Option<IVec2> {
+    self.conveyed.iter().find(|(space, _, to)| *space == parent && *to == loc).map(|(_, from, _)| *from)
+  }
+}
+
+#[test]
+fn test_conveyor() {
+  use conniver::p;
+
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  world.kinds.set_by_val("belt", p("((role conveyor) (direction e) (traction 1))"));
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  for x in 0..4 {
+    world.set_tile(space, IVec2::new(x, 0), world.kinds.get("belt"));
+  }
+  let machine = world.create_auto(Auto {
+    kind: world.kinds.get("machine"),
+    loc: IVec2::new(4, 0),
+    parent: space,
+    dim: IVec2::new(2, 1),
+    ..Auto::default()
+  });
+  let rock = world.kinds.get("rock");
+  world.set_item(space, IVec2::new(0, 0), rock);
+  world.set_item(space, IVec2::new(1, 0), rock);
+
+  // the two move together, and the front one feeds the machine at the end of the belt
+  world.update(1.0);
+  assert_eq!(world.get_item(space, IVec2::new(2, 0)), rock);
+  assert_eq!(world.get_item(space, IVec2::new(1, 0)), rock);
+  assert_eq!(world.conveyed_from(space, IVec2::new(2, 0)), Some(IVec2::new(1, 0)));
+  world.update(1.0);
+  world.update(1.0);
+  assert_eq!(world.get_item(machine, IVec2::new(0, 0)), rock);
+
+  // a full slot blocks the belt
+  world.update(1.0);
+  world.update(1.0);
+  assert_eq!(world.get_item(space, IVec2::new(3, 0)), rock);
+
+  // so does a wall at the end of a belt
+  world.set_tile(space, IVec2::new(0, 2), world.kinds.get("belt"));
+  world.set_tile(space, IVec2::new(1, 2), world.kinds.get("wall"));
+  world.set_item(space, IVec2::new(0, 2), rock);
+  world.update(1.0);
+  assert_eq!(world.get_item(space, IVec2::new(0, 2)), rock);
+  assert_eq!(world.get_item(space, IVec2::new(1, 2)), Kind(0));
+}
+
+#[test]
+fn test_conveyor_feed() {
+  use conniver::p;
+
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  world.kinds.set_by_val("belt", p("((role conveyor) (direction e) (traction 1))"));
+  world.kinds.set_by_val("rock", p("((stack 5))"));
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  world.set_tile(space, IVec2::new(0, 0), world.kinds.get("belt"));
+  world.set_tile(space, IVec2::new(0, 1), world.kinds.get("belt"));
+  let rock = world.kinds.get("rock");
+
+  // a machine at the end of the belt takes one item a tick
+  let machine = world.create_auto(Auto {
+    kind: world.kinds.get("machine"),
+    loc: IVec2::new(1, 0),
+    parent: space,
+    dim: IVec2::new(2, 1),
+    ..Auto::default()
+  });
+  world.set_stack(space, IVec2::new(0, 0), rock, 3);
+  world.update(1.0);
+  assert_eq!(world.get_count(machine, IVec2::new(0, 0)), 1);
+  assert_eq!(world.get_count(space, IVec2::new(0, 0)), 2);
+
+  // a robot standing there doesn't get fed, and neither does a machine the belt's owner may not access
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(1, 1),
+    parent: space,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+  world.set_item(space, IVec2::new(0, 1), rock);
+  world.update(1.0);
+  assert_eq!(world.get_item(robo, IVec2::ZERO), Kind(0));
+  assert_eq!(world.get_item(space, IVec2::new(0, 1)), rock);
+  assert_eq!(world.get_count(machine, IVec2::new(0, 0)), 2);
+  let red = world.forces.define("red");
+  world.get_auto_mut(machine).force = red;
+  world.update(1.0);
+  assert_eq!(world.get_count(machine, IVec2::new(0, 0)), 2);
+  assert_eq!(world.get_count(space, IVec2::new(0, 0)), 1);
+}
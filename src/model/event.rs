@@ -0,0 +1,49 @@
+use bevy::prelude::IVec2;
+
+use super::{auto::AutoNdx, kind::Kind};
+
+/// Something that happened in the world that scripts may want to hear about.
+#[derive(Clone, Debug, PartialEq)]
+pub enum WorldEvent {
+  ActionFinished(AutoNdx),
+  ActionStalled(AutoNdx, String),
+  /// An item was put into one of the auto's slots by someone else.
+  ItemArrived(AutoNdx, IVec2, Kind),
+  Produced(AutoNdx),
+  AutoCreated(AutoNdx),
+  AutoDestroyed(AutoNdx),
+}
+
+impl WorldEvent {
+  /// The name scripts subscribe with, which is also the function the interrupt calls.
+  pub fn name(&self) -> &'static str {
+    match self {
+      WorldEvent::ActionFinished(_) => "action-finished",
+      WorldEvent::ActionStalled(..) => "action-stalled",
+      WorldEvent::ItemArrived(..) => "item-arrived",
+      WorldEvent::Produced(_) => "produced",
+      WorldEvent::AutoCreated(_) => "auto-created",
+      WorldEvent::AutoDestroyed(_) => "auto-destroyed",
+    }
+  }
+
+  pub fn auto(&self) -> AutoNdx {
+    match self {
+      WorldEvent::ActionFinished(auto)
+      | WorldEvent::ActionStalled(auto, _)
+      | WorldEvent::ItemArrived(auto, ..)
+      | WorldEvent::Produced(auto)
+      | WorldEvent::AutoCreated(auto)
+      | WorldEvent::AutoDestroyed(auto) => *auto,
+    }
+  }
+
+  /// Whether every auto that can see it hears about it, rather than just the auto it happened to.
+  pub fn is_public(&self) -> bool {
+    matches!(self, WorldEvent::AutoCreated(_) | WorldEvent::AutoDestroyed(_))
+  }
+
+  pub fn is_known(name: &str) -> bool {
+    matches!(name, "action-finished" | "action-stalled" | "item-arrived" | "produced" | "auto-created" | "auto-destroyed")
+  }
+}
@@ -6,7 +6,7 @@ use bevy::prelude::IVec2;
 use conniver::p;
 
 #[cfg(test)]
-use crate::{model::{world::World, auto::{AutoNdx, Auto}}, program::{test::run100, program::ProgramSpace}};
+use crate::{model::{world::World, auto::{AutoNdx, Auto}, act::Action}, program::{test::run100, program::ProgramSpace}};
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
 pub struct ForceNdx(pub usize);
@@ -15,9 +15,37 @@ pub struct Force {
   pub name: String,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Relation {
+  Ally,
+  #[default]
+  Neutral,
+  Hostile,
+}
+
+impl Relation {
+  pub fn from_str(name: &str) -> Option<Relation> {
+    match name {
+      "ally" => Some(Relation::Ally),
+      "neutral" => Some(Relation::Neutral),
+      "hostile" => Some(Relation::Hostile),
+      _ => None,
+    }
+  }
+
+  pub fn to_str(self) -> &'static str {
+    match self {
+      Relation::Ally => "ally",
+      Relation::Neutral => "neutral",
+      Relation::Hostile => "hostile",
+    }
+  }
+}
+
 pub struct Forces {
   pub forces: Vec<Force>,
   pub forces_by_name: HashMap<String, ForceNdx>,
+  pub relations: HashMap<(ForceNdx, ForceNdx), Relation>,
 }
 
 impl Forces {
@@ -25,6 +53,7 @@ impl Forces {
     let mut result = Forces {
       forces: vec![],
       forces_by_name: HashMap::new(),
+      relations: HashMap::new(),
     };
     result.create(Force {
       name: "nature".to_string(),
@@ -42,6 +71,31 @@ impl Forces {
     ndx
   }
 
+  /// Creates the force unless one by that name already exists.
+  pub fn define(&mut self, name: &str) -> ForceNdx {
+    if let Some(ndx) = self.forces_by_name.get(name) {
+      *ndx
+    } else {
+      self.create(Force {
+        name: name.to_string(),
+      })
+    }
+  }
+
+  pub fn set_relation(&mut self, a: ForceNdx, b: ForceNdx, relation: Relation) {
+    self.relations.insert((a, b), relation);
+    self.relations.insert((b, a), relation);
+  }
+
+  /// A force is always allied with itself; otherwise forces are neutral until told otherwise.
+  pub fn relation(&self, a: ForceNdx, b: ForceNdx) -> Relation {
+    if a == b {
+      Relation::Ally
+    } else {
+      self.relations.get(&(a, b)).copied().unwrap_or_default()
+    }
+  }
+
   pub fn get(&self, name: &str) -> ForceNdx {
     if let Some(ndx) = self.forces_by_name.get(name) {
       *ndx
@@ -139,3 +193,123 @@ fn test_load_force() {
   let new_robo = AutoNdx(3);
   assert_eq!(world.get_auto(new_robo).force, robo_force);
 }
+
+#[test]
+fn test_relations() {
+  let mut forces = Forces::new_blank();
+  let nature = forces.get("nature");
+  let red = forces.define("red");
+  let blue = forces.define("blue");
+  assert_eq!(forces.define("red"), red);
+
+  assert_eq!(forces.relation(red, red), Relation::Ally);
+  assert_eq!(forces.relation(red, blue), Relation::Neutral);
+
+  forces.set_relation(red, blue, Relation::Hostile);
+  assert_eq!(forces.relation(red, blue), Relation::Hostile);
+  assert_eq!(forces.relation(blue, red), Relation::Hostile);
+  assert_eq!(forces.relation(red, nature), Relation::Neutral);
+
+  forces.set_relation(blue, red, Relation::Ally);
+  assert_eq!(forces.relation(red, blue), Relation::Ally);
+}
+
+#[test]
+fn test_relation_pick() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  let loc = IVec2::new(10, 10);
+  let rock = world.kinds.get("rock");
+  let red = world.forces.define("red");
+  let blue = world.forces.define("blue");
+  world.set_all_tiles(space, world.kinds.get("grass"));
+
+  let table = world.create_auto(Auto {
+    kind: world.kinds.get("table"),
+    loc,
+    parent: space,
+    force: blue,
+    ..Auto::default()
+  });
+  world.set_item(table, IVec2::new(0, 0), rock);
+
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc,
+    parent: space,
+    force: red,
+    ..Auto::default()
+  });
+
+  // neutral autos keep their items to themselves
+  world.set_auto_action(robo, Action::Pick(rock, world.kinds.get("table")));
+  world.update(2.0);
+  assert_eq!(world.stall_message(robo), Some("Could not find rock on table.".to_string()));
+
+  world.forces.set_relation(red, blue, Relation::Ally);
+  world.update(2.0);
+  assert_eq!(world.stall_message(robo), None);
+  assert_eq!(world.get_item(robo, IVec2::new(0, 0)), rock);
+  assert_eq!(world.get_item(table, IVec2::new(0, 0)), world.kinds.nothing());
+}
+
+#[test]
+fn test_relation_place() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  let loc = IVec2::new(10, 10);
+  let rock = world.kinds.get("rock");
+  let red = world.forces.define("red");
+  let blue = world.forces.define("blue");
+  world.set_all_tiles(space, world.kinds.get("grass"));
+
+  let table = world.create_auto(Auto {
+    kind: world.kinds.get("table"),
+    loc,
+    parent: space,
+    force: blue,
+    ..Auto::default()
+  });
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc,
+    parent: space,
+    force: red,
+    ..Auto::default()
+  });
+  world.set_item(robo, IVec2::new(0, 0), rock);
+
+  // neutral autos take nothing either, whether the slot is empty or could be topped up
+  world.set_auto_action(robo, Action::Place(world.kinds.get("table")));
+  world.update(2.0);
+  assert_eq!(world.stall_message(robo), Some("Could not find empty slot on table.".to_string()));
+  world.set_item(table, IVec2::new(0, 0), rock);
+  world.update(2.0);
+  assert_eq!(world.stall_message(robo), Some("Could not find empty slot on table.".to_string()));
+
+  world.set_item(table, IVec2::new(0, 0), world.kinds.nothing());
+  world.forces.set_relation(red, blue, Relation::Ally);
+  world.update(2.0);
+  assert_eq!(world.stall_message(robo), None);
+  assert_eq!(world.get_item(table, IVec2::new(0, 0)), rock);
+  assert_eq!(world.get_item(robo, IVec2::new(0, 0)), world.kinds.nothing());
+}
+
+#[test]
+fn test_set_relation_message() {
+  let mut world = World::new_blank();
+  let space = AutoNdx(0);
+  let mut program = ProgramSpace::new(space);
+
+  program.interrupt(space, p("(do
+    (define-force red)
+    (define-force blue)
+    (set-relation red blue hostile)
+  )"));
+  run100(&mut world, &mut program, space, -1);
+
+  let red = world.forces.get("red");
+  let blue = world.forces.get("blue");
+  assert_ne!(red, blue);
+  assert_eq!(world.forces.relation(blue, red), Relation::Hostile);
+}
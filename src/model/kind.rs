@@ -1,8 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use bevy::prelude::IVec2;
 use conniver::{Val, read_object, read_ivec2, object::read_string, p};
 
+use super::dir::Dir;
+
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 pub struct Kind(pub usize);
 impl Kind {
@@ -17,6 +19,8 @@ pub enum KindRole {
   #[default]
   Item,
   Auto,
+  /// A tile that carries items on it one cell a tick toward its direction.
+  Conveyor,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -26,17 +30,30 @@ pub struct KindData {
   pub item_dim: IVec2,
   pub program: Val,
   pub traction: i32,
+  pub stack: i32,
+  pub sight: i32,
   pub role: KindRole,
+  /// Which way a conveyor carries items.
+  pub direction: Dir,
+  pub props: HashMap<String, Val>,
+}
+
+impl KindData {
+  pub fn max_stack(&self) -> i32 {
+    self.stack.max(1)
+  }
 }
 
 pub struct Kinds {
   pub kinds: Vec<KindData>,
   pub kinds_by_name: HashMap<String, Kind>,
+  /// Kinds defined so far in a reload, if one is under way.
+  reloaded: Option<HashSet<Kind>>,
 }
 
 impl Kinds {
   pub fn new_blank() -> Kinds {
-    let mut kinds = Kinds { kinds: vec![], kinds_by_name: HashMap::new() };
+    let mut kinds = Kinds { kinds: vec![], kinds_by_name: HashMap::new(), reloaded: None };
     kinds.set_by_val("nothing", p("(
       (traction 10)
     )"));
@@ -90,6 +107,11 @@ impl Kinds {
       (dim (1 1))
       (traction 5)
     )"));
+    kinds.set_by_val("inserter", p("(
+      (role auto)
+      (dim (1 1))
+      (program '(loop (transfer)))
+    )"));
 
     kinds
   }
@@ -142,6 +164,11 @@ impl Kinds {
       });
       kind
     };
+    if self.reloaded.as_mut().is_some_and(|reloaded| reloaded.insert(kind)) {
+      // a reloaded kind starts over, so keys removed from its definition don't linger
+      let name = self.name(kind);
+      self.kinds[kind.0] = KindData { name, ..Default::default() };
+    }
     let kind_data = self.get_data_mut(kind);
     let mut new_name = None;
 
@@ -153,6 +180,7 @@ impl Kinds {
           "tile" => KindRole::Tile,
           "item" => KindRole::Item,
           "auto" => KindRole::Auto,
+          "conveyor" => KindRole::Conveyor,
           _ => {
             println!("bad role: {val:?}");
             KindRole::Item
@@ -172,9 +200,26 @@ impl Kinds {
           println!("bad traction: {val:?}");
         },
 
+        "stack" => if let Val::Num(i) = val {
+          kind_data.stack = *i as i32;
+        } else {
+          println!("bad stack: {val:?}");
+        },
+
+        "sight" => if let Val::Num(i) = val {
+          kind_data.sight = *i as i32;
+        } else {
+          println!("bad sight: {val:?}");
+        },
+
         "program" => kind_data.program = val.clone(),
 
-        _ => {}
+        "direction" => kind_data.direction = Dir::from_str(&read_string(val)),
+
+        // anything else is a designer-defined property, for scripts to read with kind-prop
+        _ => {
+          kind_data.props.insert(key.to_string(), val.clone());
+        }
       }
     });
 
@@ -187,6 +232,15 @@ impl Kinds {
     }
   }
 
+  /// Starts or ends a reload, during which each kind's first definition replaces its old one.
+  pub fn set_reloading(&mut self, reloading: bool) {
+    self.reloaded = if reloading { Some(HashSet::new()) } else { None };
+  }
+
+  pub fn prop(&self, kind: Kind, key: &str) -> Val {
+    self.get_data(kind).props.get(key).cloned().unwrap_or_default()
+  }
+
   pub fn name(&self, kind: Kind) -> String {
     self.kinds[kind.0].name.clone()
   }
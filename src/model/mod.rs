@@ -1,12 +1,16 @@
 pub mod act;
 pub mod auto;
 pub mod bitfield;
+pub mod conveyor;
 pub mod dir;
+pub mod event;
 pub mod force;
 pub mod kind;
 pub mod pattern;
 pub mod route;
 pub mod slot;
+pub mod task;
+pub mod vision;
 pub mod world;
 
 #[cfg(test)]
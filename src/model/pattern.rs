@@ -1,4 +1,4 @@
-
+use std::collections::HashSet;
 
 use conniver::{Val, read_object};
 
@@ -14,6 +14,8 @@ pub struct Pattern {
   pub for_kind: Kind,
   pub input: Vec<Kind>,
   pub output: Vec<Kind>,
+  pub input_counts: Vec<i32>,
+  pub output_counts: Vec<i32>,
 }
 
 impl Pattern {
@@ -22,9 +24,55 @@ impl Pattern {
       for_kind: Kind(1),
       input: vec![],
       output: vec![],
+      input_counts: vec![],
+      output_counts: vec![],
+    }
+  }
+
+  /// How many of the slot's kind the pattern wants (or makes). Missing counts are one of anything but nothing.
+  fn count(kinds: &[Kind], counts: &[i32], ndx: usize) -> i32 {
+    if kinds[ndx] == Kind(0) {
+      0
+    } else {
+      counts.get(ndx).copied().unwrap_or(1)
     }
   }
 
+  pub fn matches(&self, holding: &[Kind], counts: &[i32]) -> bool {
+    if self.input.len() != holding.len() {
+      return false;
+    }
+    for (ndx, input) in self.input.iter().enumerate() {
+      let have = counts.get(ndx).copied().unwrap_or(0);
+      let want = Pattern::count(&self.input, &self.input_counts, ndx);
+      if *input != holding[ndx] || have < want {
+        return false;
+      }
+      // whatever is left over has to be able to share the slot with the output
+      let out = self.output.get(ndx).copied().unwrap_or(Kind(0));
+      if have > want && out != Kind(0) && out != *input {
+        return false;
+      }
+    }
+    true
+  }
+
+  /// Returns the stacks left in each slot after consuming the inputs and adding the outputs.
+  pub fn apply(&self, holding: &[Kind], counts: &[i32]) -> Vec<(Kind, i32)> {
+    let mut result = vec![];
+    for (ndx, kind) in holding.iter().enumerate() {
+      let have = counts.get(ndx).copied().unwrap_or(0);
+      let left = have - Pattern::count(&self.input, &self.input_counts, ndx);
+      let out = self.output.get(ndx).copied().unwrap_or(Kind(0));
+      if out == Kind(0) {
+        result.push((*kind, left));
+      } else {
+        result.push((out, left + Pattern::count(&self.output, &self.output_counts, ndx)));
+      }
+    }
+    result
+  }
+
   pub fn from_val(val: &Val, world: &World) -> Pattern {
     let mut pattern = Pattern::new();
     pattern.for_kind = Kind(1);
@@ -51,18 +99,24 @@ impl Pattern {
       //return Some(Val::String("usage: (define-pattern (for ...) (in ...) (out ...) ...)".to_owned()));
     }
 
+    // each entry is either a kind, or a (kind count) stack
     let in_out = in_out.into_iter().map(|v| {
       v.into_iter().map(|v| {
-        if let Val::Sym(kind) = v {
-          world.kinds.get(&kind)
-        } else {
-          Kind(1)
+        match v {
+          Val::Sym(kind) => (world.kinds.get(&kind), 1),
+          Val::List(stack) => match (stack.get(0), stack.get(1)) {
+            (Some(Val::Sym(kind)), Some(Val::Num(count))) => (world.kinds.get(kind), *count as i32),
+            _ => (Kind(1), 1),
+          },
+          _ => (Kind(1), 1),
         }
       }).collect()
-    }).collect::<Vec<Vec<Kind>>>();
+    }).collect::<Vec<Vec<(Kind, i32)>>>();
 
-    pattern.input = in_out[0].clone();
-    pattern.output = in_out[1].clone();
+    pattern.input = in_out[0].iter().map(|(kind, _)| *kind).collect();
+    pattern.input_counts = in_out[0].iter().map(|(_, count)| *count).collect();
+    pattern.output = in_out[1].iter().map(|(kind, _)| *kind).collect();
+    pattern.output_counts = in_out[1].iter().map(|(_, count)| *count).collect();
     pattern
   }
 }
@@ -70,11 +124,13 @@ impl Pattern {
 #[derive(Debug)]
 pub struct Patterns {
   pub patterns: Vec<Pattern>,
+  /// Kinds whose patterns have been defined so far in a reload, if one is under way.
+  reloaded: Option<HashSet<Kind>>,
 }
 
 impl Patterns {
   pub fn new_blank() -> Patterns {
-    Patterns { patterns: vec![] }
+    Patterns { patterns: vec![], reloaded: None }
   }
 
   #[cfg(test)]
@@ -85,33 +141,49 @@ impl Patterns {
           for_kind: kinds.get("machine"),
           input: vec![kinds.get("rock"), Kind(0)],
           output: vec![kinds.get("thing"), Kind(0)],
+          ..Pattern::new()
         },
         Pattern {
           for_kind: kinds.get("machine"),
           input: vec![kinds.get("thing"), kinds.get("rock")],
           output: vec![kinds.get("widget"), Kind(0)], 
+          ..Pattern::new()
         },
       ],
+      reloaded: None,
     }
   }
 
+  /// Adds a pattern, replacing any pattern for the same kind and inputs so that reloading is idempotent.
+  /// During a reload, a kind's first pattern replaces all of its old ones.
   pub fn add(&mut self, pattern: Pattern) {
-    self.patterns.push(pattern);
+    if self.reloaded.as_mut().is_some_and(|reloaded| reloaded.insert(pattern.for_kind)) {
+      self.patterns.retain(|old| old.for_kind != pattern.for_kind);
+    }
+    let existing = self.patterns.iter_mut().find(|old| {
+      old.for_kind == pattern.for_kind && old.input == pattern.input && old.input_counts == pattern.input_counts
+    });
+    if let Some(existing) = existing {
+      *existing = pattern;
+    } else {
+      self.patterns.push(pattern);
+    }
+  }
+
+  pub fn set_reloading(&mut self, reloading: bool) {
+    self.reloaded = if reloading { Some(HashSet::new()) } else { None };
   }
 
+  #[cfg(test)]
   pub fn get(&self, kind: Kind, holding: &Vec<Kind>) -> Option<Pattern> {
+    let counts = holding.iter().map(|item| if *item == Kind(0) { 0 } else { 1 }).collect::<Vec<i32>>();
+    self.get_stacked(kind, holding, &counts)
+  }
+
+  pub fn get_stacked(&self, kind: Kind, holding: &[Kind], counts: &[i32]) -> Option<Pattern> {
     for pattern in &self.patterns {
-      if pattern.for_kind == kind && pattern.input.len() == holding.len() {
-        let mut found = true;
-        for (i, input) in pattern.input.iter().enumerate() {
-          if *input != holding[i] {
-            found = false;
-            break;
-          }
-        }
-        if found {
-          return Some(pattern.clone());
-        }
+      if pattern.for_kind == kind && pattern.matches(holding, counts) {
+        return Some(pattern.clone());
       }
     }
     None
@@ -33,8 +33,11 @@ pub fn route(world: &World, auto_ndx: AutoNdx, dest: IVec2) -> Option<Vec<Dir>>
   let start = auto.loc;
   let kind = auto.kind;
   let parent = auto.parent;
+  let force = auto.force;
 
   if start == dest { return Some(Vec::new()); }
+  // routes only go through cells the auto's force has seen
+  if !world.cell_seen(force, parent, dest) { return None; }
 
   let mut heap = BinaryHeap::new();
   let mut dist = HashMap::<IVec2, usize>::new();
@@ -59,7 +62,7 @@ pub fn route(world: &World, auto_ndx: AutoNdx, dest: IVec2) -> Option<Vec<Dir>>
     // a lower cost going through this node
     for dir in Dir::all() {
       let next_pos = pos + dir.to_ivec2();
-      if !world.traction_valid(parent, kind, next_pos) { continue; }
+      if !world.traction_valid(parent, kind, next_pos) || !world.cell_seen(force, parent, next_pos) { continue; }
       let to = RouteNode { cost: cost + 1, pos: next_pos };
 
       // If so, add it to the frontier and continue
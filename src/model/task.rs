@@ -0,0 +1,291 @@
+use bevy::prelude::IVec2;
+
+use super::{act::Action, auto::{AutoNdx, auto_action_finished}, force::ForceNdx, kind::Kind, route::route, slot::Slot, world::World};
+
+#[cfg(test)]
+use super::auto::Auto;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TaskKind {
+  /// A machine's slot needs an item brought to it.
+  Supply,
+  /// An item in a machine's slot is ready to be taken away.
+  Collect,
+}
+
+impl TaskKind {
+  pub fn from_str(name: &str) -> Option<TaskKind> {
+    match name {
+      "supply" => Some(TaskKind::Supply),
+      "collect" => Some(TaskKind::Collect),
+      _ => None,
+    }
+  }
+
+  pub fn to_str(self) -> &'static str {
+    match self {
+      TaskKind::Supply => "supply",
+      TaskKind::Collect => "collect",
+    }
+  }
+}
+
+/// A request for a robot to carry an item to or from a machine's slot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Task {
+  pub id: u64,
+  pub kind: TaskKind,
+  /// Only robots of the machine's force take its tasks.
+  pub force: ForceNdx,
+  pub slot: Slot,
+  pub item: Kind,
+  pub claimed: Option<AutoNdx>,
+  /// Where the claiming robot picks the item up and puts it down, in the parent's cells.
+  pub from: IVec2,
+  pub to: IVec2,
+  /// Whether the claiming robot has picked the item up yet.
+  pub picked: bool,
+}
+
+/// The world's logistics tasks, posted by machines and scripts and claimed by idle robots.
+#[derive(Default)]
+pub struct TaskBoard {
+  pub tasks: Vec<Task>,
+  next_id: u64,
+}
+
+impl TaskBoard {
+  /// Posts a task, unless the same one is already posted, returning its id.
+  pub fn post(&mut self, kind: TaskKind, force: ForceNdx, slot: Slot, item: Kind) -> u64 {
+    if let Some(task) = self.tasks.iter().find(|task| task.kind == kind && task.slot == slot && task.item == item) {
+      return task.id;
+    }
+    self.next_id += 1;
+    self.tasks.push(Task {
+      id: self.next_id,
+      kind,
+      force,
+      slot,
+      item,
+      claimed: None,
+      from: IVec2::ZERO,
+      to: IVec2::ZERO,
+      picked: false,
+    });
+    self.next_id
+  }
+
+  pub fn get(&self, id: u64) -> Option<&Task> {
+    self.tasks.iter().find(|task| task.id == id)
+  }
+
+  pub fn claimed_by(&self, auto: AutoNdx) -> Option<&Task> {
+    self.tasks.iter().find(|task| task.claimed == Some(auto))
+  }
+
+  /// Puts a claimed task back on the board for another robot.
+  pub fn release(&mut self, id: u64) -> bool {
+    if let Some(task) = self.tasks.iter_mut().find(|task| task.id == id && task.claimed.is_some()) {
+      task.claimed = None;
+      true
+    } else {
+      false
+    }
+  }
+
+  pub fn release_auto(&mut self, auto: AutoNdx) {
+    for task in self.tasks.iter_mut().filter(|task| task.claimed == Some(auto)) {
+      task.claimed = None;
+    }
+  }
+
+  /// Takes a task off the board.
+  pub fn remove(&mut self, id: u64) -> bool {
+    let len = self.tasks.len();
+    self.tasks.retain(|task| task.id != id);
+    self.tasks.len() < len
+  }
+}
+
+impl World {
+  /// Claims the nearest open task of a robot's force that it can see a way to carry out, returning its id.
+  pub fn claim_task(&mut self, auto: AutoNdx) -> Option<u64> {
+    if let Some(task) = self.tasks.claimed_by(auto) {
+      return Some(task.id);
+    }
+    let robot = self.get_auto(auto);
+    let (force, loc) = (robot.force, robot.loc);
+    let mut best = None;
+    for (ndx, task) in self.tasks.tasks.iter().enumerate() {
+      if task.claimed.is_some() || task.force != force {
+        continue;
+      }
+      if let Some((from, to)) = self.task_plan(auto, task) {
+        let dist = (from - loc).abs();
+        let dist = dist.x + dist.y;
+        if !matches!(best, Some((_, best_dist, _, _)) if best_dist <= dist) {
+          best = Some((ndx, dist, from, to));
+        }
+      }
+    }
+    let (ndx, _, from, to) = best?;
+    let task = &mut self.tasks.tasks[ndx];
+    task.claimed = Some(auto);
+    task.from = from;
+    task.to = to;
+    task.picked = false;
+    Some(task.id)
+  }
+
+  /// Where a robot would pick up and put down a task's item: a supply comes from the nearest stack of
+  /// it on the ground, and a collection goes to the nearest empty cell.
+  pub fn task_plan(&self, auto: AutoNdx, task: &Task) -> Option<(IVec2, IVec2)> {
+    let robot = self.get_auto(auto);
+    let machine = self.get_auto(task.slot.0);
+    if machine.parent != robot.parent {
+      return None;
+    }
+    let parent = self.get_auto(robot.parent);
+    let machine_cell = machine.loc + task.slot.1;
+    let nearest = |found: &dyn Fn(IVec2, Kind) -> bool, near: IVec2| {
+      parent.items.iter().enumerate()
+        .map(|(ndx, item)| (parent.ndx_to_loc(ndx), *item))
+        .filter(|(loc, item)| found(*loc, *item))
+        .min_by_key(|(loc, _)| {
+          let dist = (*loc - near).abs();
+          dist.x + dist.y
+        })
+        .map(|(loc, _)| loc)
+    };
+    match task.kind {
+      TaskKind::Supply => {
+        let from = nearest(&|_, item| item == task.item, robot.loc)?;
+        Some((from, machine_cell))
+      }
+      TaskKind::Collect => {
+        if self.get_item(task.slot.0, task.slot.1) != task.item {
+          return None;
+        }
+        let empty = |loc: IVec2, item: Kind| {
+          item == Kind(0) && self.get_slots(robot.parent, loc).is_empty() && self.traction_valid(robot.parent, robot.kind, loc)
+        };
+        let to = nearest(&empty, machine_cell)?;
+        Some((machine_cell, to))
+      }
+    }
+  }
+
+  /// Takes a robot one action further through its claimed task: routing to where the item is, picking
+  /// it up, routing to where it goes, and putting it down. Returns true once it's delivered.
+  pub fn work_task(&mut self, auto: AutoNdx, id: u64) -> Result<bool, String> {
+    let task = self.tasks.get(id).cloned().ok_or_else(|| format!("error: no task {id}"))?;
+    // whatever the robot held before it picked the task's item up doesn't count
+    let (dest, action) = match (task.picked, task.kind) {
+      (false, _) => (task.from, Action::Pick(task.item, Kind(1))),
+      (true, TaskKind::Supply) => (task.to, Action::Place(self.get_auto(task.slot.0).kind)),
+      (true, TaskKind::Collect) => (task.to, Action::Place(Kind(1))),
+    };
+    if self.get_auto(auto).loc != dest {
+      let dir = route(self, auto, dest).and_then(|route| route.first().copied()).ok_or("no route")?;
+      self.task_action(auto, Action::Step(dir));
+      return Ok(false);
+    }
+    let done = self.task_action(auto, action);
+    if done && !task.picked {
+      if let Some(task) = self.tasks.tasks.iter_mut().find(|task| task.id == id) {
+        task.picked = true;
+      }
+      return Ok(false);
+    }
+    Ok(done)
+  }
+
+  /// Starts an action, or, if it's already started, reports whether it's finished.
+  fn task_action(&mut self, auto: AutoNdx, action: Action) -> bool {
+    if self.get_auto_action(auto) != action {
+      self.set_auto_action(auto, action);
+      false
+    } else if self.get_auto(auto).flags.get(auto_action_finished) {
+      self.set_auto_action(auto, Action::Stop);
+      true
+    } else {
+      false
+    }
+  }
+}
+
+/// A two-cell table and a robot to supply it, on open ground.
+#[cfg(test)]
+pub fn test_task_world() -> (World, AutoNdx, AutoNdx) {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  let table = world.create_auto(Auto {
+    kind: world.kinds.get("table"),
+    loc: IVec2::new(5, 5),
+    parent: space,
+    dim: IVec2::new(2, 1),
+    ..Auto::default()
+  });
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(1, 1),
+    parent: space,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+  (world, table, robo)
+}
+
+#[test]
+fn test_task_board() {
+  let (mut world, table, robo) = test_task_world();
+  let space = AutoNdx(0);
+  let rock = world.kinds.get("rock");
+  let force = world.get_auto(table).force;
+
+  // nothing to supply it from yet
+  let id = world.tasks.post(TaskKind::Supply, force, Slot(table, IVec2::new(1, 0)), rock);
+  assert_eq!(world.tasks.post(TaskKind::Supply, force, Slot(table, IVec2::new(1, 0)), rock), id);
+  assert_eq!(world.claim_task(robo), None);
+
+  world.set_item(space, IVec2::new(3, 1), rock);
+  world.set_item(space, IVec2::new(9, 9), rock);
+  assert_eq!(world.claim_task(robo), Some(id));
+  let task = world.tasks.get(id).unwrap();
+  assert_eq!((task.claimed, task.from, task.to), (Some(robo), IVec2::new(3, 1), IVec2::new(6, 5)));
+
+  let mut steps = 0;
+  while !world.work_task(robo, id).unwrap() {
+    world.update(1.0);
+    steps += 1;
+    assert!(steps < 100);
+  }
+  assert_eq!(world.get_item(table, IVec2::new(1, 0)), rock);
+  assert_eq!(world.get_item(space, IVec2::new(3, 1)), Kind(0));
+
+  world.tasks.release_auto(robo);
+  assert_eq!(world.tasks.get(id).unwrap().claimed, None);
+  assert!(world.tasks.remove(id));
+  assert!(world.tasks.tasks.is_empty());
+}
+
+#[test]
+fn test_collect_task() {
+  let (mut world, table, robo) = test_task_world();
+  let rock = world.kinds.get("rock");
+  let force = world.get_auto(table).force;
+
+  // a robot already holding the item still has to go and collect it
+  world.set_item(robo, IVec2::ZERO, rock);
+  world.set_item(table, IVec2::ZERO, rock);
+  let id = world.tasks.post(TaskKind::Collect, force, Slot(table, IVec2::ZERO), rock);
+  assert_eq!(world.claim_task(robo), Some(id));
+  let mut steps = 0;
+  while !world.work_task(robo, id).unwrap() {
+    world.update(1.0);
+    steps += 1;
+    assert!(steps < 100);
+  }
+  assert_eq!(world.get_item(table, IVec2::ZERO), Kind(0));
+}
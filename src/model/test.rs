@@ -2,7 +2,7 @@
 use bevy::prelude::IVec2;
 use conniver::{p};
 
-use crate::model::{auto::{AutoNdx, Auto, auto_action_finished, auto_alive}, world::World, act::Action, dir::Dir, kind::{Kind, KindRole}};
+use crate::model::{auto::{AutoNdx, Auto, auto_action_finished, auto_alive}, world::World, act::Action, dir::Dir, kind::{Kind, KindRole}, pattern::Pattern, force::Relation, event::WorldEvent, slot::Slot};
 
 use super::kind::Kinds;
 
@@ -332,11 +332,14 @@ fn test_produce() {
 fn test_fire() {
   let mut world = World::new_test();
   let space = AutoNdx(0);
+  let red = world.forces.define("red");
+  let blue = world.forces.define("blue");
   let loc1 = IVec2::new(10, 10);
   let robo1 = world.create_auto(Auto {
     kind: world.kinds.get("robo"),
     loc: loc1,
     parent: space,
+    force: red,
     dim: IVec2::new(1, 1),
     ..Auto::default()
   });
@@ -346,11 +349,20 @@ fn test_fire() {
     kind: world.kinds.get("robo"),
     loc: loc2,
     parent: space,
+    force: blue,
     dim: IVec2::new(1, 1),
     ..Auto::default()
   });
   assert_eq!(world.get_auto(robo2).flags.get(auto_alive), true);
 
+  // neutral autos are left alone
+  world.set_auto_action(robo1, Action::Fire(robo2));
+  world.update(2.0);
+  assert_eq!(world.stall_message(robo1), Some("Target is not hostile.".to_string()));
+  assert_eq!(world.get_auto(robo2).flags.get(auto_alive), true);
+
+  world.forces.set_relation(red, blue, Relation::Hostile);
+
   world.set_auto_action(robo1, Action::Fire(robo2));
   world.update(2.0);
   assert_eq!(world.stall_message(robo1), None);
@@ -362,6 +374,7 @@ fn test_fire() {
     kind: world.kinds.get("robo"),
     loc: loc3,
     parent: space,
+    force: blue,
     dim: IVec2::new(1, 1),
     ..Auto::default()
   });
@@ -528,4 +541,355 @@ fn test_kind_names() {
 
   assert_eq!(nothing, kinds.get("ground"));
   assert_eq!(missingno, kinds.get("any"));
-}
\ No newline at end of file
+}
+#[test]
+fn test_stacks() {
+  let mut world = World::new_test();
+  world.kinds.set_by_val("rock", p("(
+    (stack 5)
+  )"));
+  let space = AutoNdx(0);
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  let loc = IVec2::new(10, 10);
+  let hand = IVec2::new(0, 0);
+  let rock = world.kinds.get("rock");
+  let nothing = world.kinds.nothing();
+  assert_eq!(world.kinds.get_data(rock).max_stack(), 5);
+  assert_eq!(world.kinds.get_data(nothing).max_stack(), 1);
+  // stacks never go over the kind's max
+  world.set_stack(space, loc, rock, 7);
+  assert_eq!(world.get_count(space, loc), 5);
+
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc,
+    parent: space,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+
+  // the hand only holds a full stack, the rest stays on the ground
+  world.set_stack(robo, hand, rock, 3);
+  world.set_auto_action(robo, Action::Pick(rock, nothing));
+  world.update(2.0);
+  assert_eq!(world.stall_message(robo), None);
+  assert_eq!(world.get_count(robo, hand), 5);
+  assert_eq!(world.get_count(space, loc), 3);
+
+  world.set_auto_action(robo, Action::Pick(rock, nothing));
+  world.update(2.0);
+  assert_eq!(world.stall_message(robo), Some("No room in hand for rock.".to_string()));
+
+  // placing tops up the stack on the ground
+  world.set_auto_action(robo, Action::Place(nothing));
+  world.update(2.0);
+  assert_eq!(world.stall_message(robo), None);
+  assert_eq!(world.get_item(space, loc), rock);
+  assert_eq!(world.get_count(space, loc), 5);
+  assert_eq!(world.get_item(robo, hand), rock);
+  assert_eq!(world.get_count(robo, hand), 3);
+
+  world.set_stack(robo, hand, rock, 0);
+  assert_eq!(world.get_item(robo, hand), nothing);
+}
+
+#[test]
+fn test_produce_stacks() {
+  let mut world = World::new_test();
+  world.kinds.set_by_val("rock", p("((stack 10))"));
+  world.kinds.set_by_val("thing", p("((stack 10))"));
+  let space = AutoNdx(0);
+  let rock = world.kinds.get("rock");
+  let thing = world.kinds.get("thing");
+  let machine = world.kinds.get("machine");
+  let nothing = world.kinds.nothing();
+
+  world.patterns.add(Pattern::from_val(&p("(
+    (for machine)
+    (in ((rock 3) nothing))
+    (out ((thing 2) nothing))
+  )"), &world));
+
+  let machine_ndx = world.create_auto(Auto {
+    kind: machine,
+    loc: IVec2::new(10, 10),
+    parent: space,
+    ..Auto::default()
+  });
+
+  // not enough rocks yet
+  world.set_stack(machine_ndx, IVec2::new(0, 0), rock, 2);
+  world.set_auto_action(machine_ndx, Action::Produce);
+  world.update(2.0);
+  assert!(world.stall_message(machine_ndx).is_some());
+
+  world.set_stack(machine_ndx, IVec2::new(0, 0), rock, 3);
+  world.set_auto_action(machine_ndx, Action::Produce);
+  world.update(2.0);
+  assert_eq!(world.stall_message(machine_ndx), None);
+  assert_eq!(world.get_items(machine_ndx), vec![thing, nothing]);
+  assert_eq!(world.get_counts(machine_ndx), vec![2, 0]);
+
+  // output that wouldn't fit in a stack isn't made, and the inputs aren't used up
+  world.kinds.set_by_val("thing", p("((stack 1))"));
+  world.set_stack(machine_ndx, IVec2::new(0, 0), rock, 3);
+  world.set_auto_action(machine_ndx, Action::Produce);
+  world.update(2.0);
+  assert_eq!(world.stall_message(machine_ndx), Some("No room for 2 thing.".to_string()));
+  assert_eq!(world.get_items(machine_ndx), vec![rock, nothing]);
+  assert_eq!(world.get_counts(machine_ndx), vec![3, 0]);
+}
+
+#[test]
+fn test_kind_props() {
+  let mut kinds = Kinds::new_test();
+  kinds.set_by_val("rock", p("(
+    (value 12)
+    (description \"a grey rock\")
+    (tags (heavy mineral))
+  )"));
+  let rock = kinds.get("rock");
+  assert_eq!(kinds.prop(rock, "value"), p("12"));
+  assert_eq!(kinds.prop(rock, "description"), p("\"a grey rock\""));
+  assert_eq!(kinds.prop(rock, "tags"), p("(heavy mineral)"));
+  assert!(kinds.prop(rock, "weight").is_nil());
+
+  // known keys are not props
+  assert!(kinds.prop(rock, "scene").is_nil());
+}
+
+#[test]
+fn test_pattern_redefine() {
+  let mut world = World::new_test();
+  let machine = world.kinds.get("machine");
+  let rock = world.kinds.get("rock");
+  let widget = world.kinds.get("widget");
+  let nothing = world.kinds.nothing();
+  assert_eq!(world.patterns.len(), 2);
+
+  // reloading the same inputs replaces the output instead of adding a duplicate
+  world.patterns.add(Pattern::from_val(&p("(
+    (for machine)
+    (in (rock nothing))
+    (out (widget nothing))
+  )"), &world));
+  assert_eq!(world.patterns.len(), 2);
+  let pattern = world.patterns.get(machine, &vec![rock, nothing]).unwrap();
+  assert_eq!(pattern.output, vec![widget, nothing]);
+}
+
+#[test]
+fn test_reload_replaces() {
+  let mut world = World::new_test();
+  let machine = world.kinds.get("machine");
+  let rock = world.kinds.get("rock");
+  let thing = world.kinds.get("thing");
+  let nothing = world.kinds.nothing();
+  world.kinds.set_by_val("rock", p("((stack 5) (value 3))"));
+
+  world.set_reloading(true);
+  world.kinds.set_by_val("rock", p("((scene \"model/ore.glb#Scene0\"))"));
+  world.kinds.set_by_val("rock", p("((traction 2))"));
+  world.patterns.add(Pattern::from_val(&p("(
+    (for machine)
+    (in (thing nothing))
+    (out (rock nothing))
+  )"), &world));
+  world.set_reloading(false);
+
+  // removed keys go, and later definitions in the same reload add to the first
+  let data = world.kinds.get_data(rock);
+  assert_eq!((data.max_stack(), data.traction, data.scene.as_str()), (1, 2, "model/ore.glb#Scene0"));
+  assert!(world.kinds.prop(rock, "value").is_nil());
+  assert_eq!(world.kinds.get("rock"), rock);
+
+  // the machine's old patterns are replaced rather than added to
+  assert_eq!(world.patterns.len(), 1);
+  assert!(world.patterns.get(machine, &vec![rock, nothing]).is_none());
+  assert!(world.patterns.get(machine, &vec![thing, nothing]).is_some());
+}
+
+#[test]
+fn test_vision() {
+  let mut world = World::new_test();
+  world.kinds.set_by_val("robo", p("((sight 2))"));
+  let space = AutoNdx(0);
+  let red = world.forces.define("red");
+  let blue = world.forces.define("blue");
+  let earth = world.create_auto(Auto {
+    kind: world.kinds.get("earth"),
+    parent: space,
+    dim: IVec2::new(20, 20),
+    ..Auto::default()
+  });
+  world.set_all_tiles(earth, world.kinds.get("grass"));
+
+  let scout = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(5, 5),
+    parent: earth,
+    force: red,
+    ..Auto::default()
+  });
+  let stranger = world.create_auto(Auto {
+    kind: world.kinds.get("table"),
+    loc: IVec2::new(15, 15),
+    parent: earth,
+    force: blue,
+    ..Auto::default()
+  });
+  world.update(0.1);
+
+  assert!(world.cell_visible(red, earth, IVec2::new(5, 7)));
+  assert!(!world.cell_visible(red, earth, IVec2::new(7, 7)));
+  assert!(!world.auto_visible(red, stranger));
+  assert!(world.auto_visible(blue, stranger));
+  assert!(world.slot_seen(red, scout, IVec2::new(0, 0)));
+
+  // once out of sight, cells are remembered but autos there are not visible
+  world.get_auto_mut(scout).loc = IVec2::new(14, 15);
+  world.update(0.1);
+  assert!(world.auto_visible(red, stranger));
+  assert!(world.slot_seen(red, stranger, IVec2::new(1, 0)));
+  world.get_auto_mut(scout).loc = IVec2::new(5, 5);
+  world.update(0.1);
+  assert!(!world.auto_visible(red, stranger));
+  assert!(!world.cell_visible(red, earth, IVec2::new(15, 15)));
+  assert!(world.cell_seen(red, earth, IVec2::new(15, 15)));
+  assert!(!world.cell_seen(red, earth, IVec2::new(19, 0)));
+}
+
+#[test]
+fn test_route_fog() {
+  use crate::model::route::route;
+
+  let mut world = World::new_test();
+  world.kinds.set_by_val("robo", p("((sight 3))"));
+  let space = AutoNdx(0);
+  let red = world.forces.define("red");
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  for y in 7..=13 {
+    world.set_tile(space, IVec2::new(11, y), world.kinds.get("wall"));
+  }
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(10, 10),
+    parent: space,
+    force: red,
+    ..Auto::default()
+  });
+  world.update(0.1);
+
+  // the way around the wall hasn't been seen yet
+  assert!(world.cell_seen(red, space, IVec2::new(12, 10)));
+  assert_eq!(route(&world, robo, IVec2::new(12, 10)), None);
+
+  world.kinds.set_by_val("robo", p("((sight 5))"));
+  world.update(0.1);
+  assert_eq!(route(&world, robo, IVec2::new(12, 10)).map(|route| route.len()), Some(10));
+}
+
+#[test]
+fn test_world_events() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  let loc = IVec2::new(10, 10);
+  let rock = world.kinds.get("rock");
+  let machine = world.kinds.get("machine");
+  let machine_ndx = world.create_auto(Auto {
+    kind: machine,
+    loc,
+    parent: space,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc,
+    items: vec![rock],
+    parent: space,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+  assert!(world.events.contains(&WorldEvent::AutoCreated(robo)));
+  world.events.clear();
+
+  world.set_auto_action(robo, Action::Place(machine));
+  world.update(2.0);
+  assert_eq!(world.events, vec![
+    WorldEvent::ItemArrived(machine_ndx, IVec2::new(0, 0), rock),
+    WorldEvent::ActionFinished(robo),
+  ]);
+
+  // stopping afterwards, as the message handlers do, isn't another action finishing, and neither is a
+  // new auto settling in
+  world.set_auto_action(robo, Action::Stop);
+  world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(3, 3),
+    parent: space,
+    ..Auto::default()
+  });
+  world.update(2.0);
+  world.update(2.0);
+  let finished = world.events.iter().filter(|event| matches!(event, WorldEvent::ActionFinished(_))).count();
+  assert_eq!(finished, 1);
+  world.events.clear();
+
+  // a stall is reported once, not every time the action is retried
+  world.set_auto_action(robo, Action::Place(machine));
+  world.update(2.0);
+  world.update(2.0);
+  assert_eq!(world.events, vec![
+    WorldEvent::ActionStalled(robo, "Cannot place nothing.".to_string()),
+  ]);
+}
+
+#[test]
+fn test_rotation() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  let rock = world.kinds.get("rock");
+  let table = world.create_auto_from_val(p("((kind table) (loc 5 5) (parent 0))"));
+  world.set_item(table, IVec2::new(1, 0), rock);
+  assert_eq!(world.get_slots(space, IVec2::new(6, 5)), vec![Slot(table, IVec2::new(1, 0))]);
+
+  // a quarter turn clockwise stands it on its side, with its east end now to the south
+  world.set_auto_action(table, Action::Rotate(Dir::East));
+  world.update(2.0);
+  let table_data = world.get_auto(table);
+  assert_eq!(table_data.facing, Dir::East);
+  assert_eq!(table_data.footprint(), IVec2::new(1, 2));
+  assert!(world.get_slots(space, IVec2::new(6, 5)).is_empty());
+  assert_eq!(world.get_slots(space, IVec2::new(5, 6)), vec![Slot(table, IVec2::new(0, 1))]);
+  assert_eq!(world.get_item(table, IVec2::new(0, 0)), rock);
+  assert_eq!(table_data.ndx_to_loc(1), IVec2::new(0, 0));
+  for ndx in 0..2 {
+    assert_eq!(table_data.get_ndx(table_data.ndx_to_loc(ndx)), ndx as i32);
+  }
+
+  let turned = world.create_auto_from_val(p("((kind table) (loc 8 8) (parent 0) (facing s))"));
+  assert_eq!(world.get_auto(turned).facing, Dir::South);
+  assert_eq!(world.get_auto(turned).ndx_to_loc(0), IVec2::new(1, 0));
+
+  // it won't turn onto another auto or off the edge
+  world.create_auto_from_val(p("((kind widget) (loc 8 9) (parent 0))"));
+  world.set_auto_action(turned, Action::Rotate(Dir::East));
+  world.update(2.0);
+  assert_eq!(world.stall_message(turned), Some("Could not turn: widget is in the way at (8,9).".to_string()));
+  assert_eq!(world.get_auto(turned).facing, Dir::South);
+  let bottom = world.get_auto(space).footprint().y - 1;
+  let edge = world.create_auto_from_val(p(&format!("((kind table) (loc 0 {bottom}) (parent 0))")));
+  world.set_auto_action(edge, Action::Rotate(Dir::West));
+  world.update(2.0);
+  assert_eq!(world.stall_message(edge), Some(format!("Could not turn: (0,{}) is off the edge.", bottom + 1)));
+  assert_eq!(world.get_auto(edge).facing, Dir::North);
+
+  // and stepping moves it without turning it
+  world.set_auto_action(turned, Action::Step(Dir::South));
+  world.update(2.0);
+  assert_eq!(world.get_auto(turned).loc, IVec2::new(8, 7));
+  assert_eq!(world.get_auto(turned).facing, Dir::South);
+}
@@ -0,0 +1,56 @@
+use std::collections::{HashMap, HashSet};
+
+use bevy::prelude::IVec2;
+
+use super::{auto::{Auto, AutoNdx, auto_alive}, force::ForceNdx, kind::Kinds};
+
+/// A location in the grid of some auto, usually the ground of a parent.
+pub type Cell = (AutoNdx, IVec2);
+
+#[derive(Default, Debug)]
+pub struct ForceVision {
+  pub visible: HashSet<Cell>,
+  pub seen: HashSet<Cell>,
+}
+
+/// What each force can see right now, and what it has ever seen.
+#[derive(Default, Debug)]
+pub struct Vision {
+  pub forces: HashMap<ForceNdx, ForceVision>,
+}
+
+impl Vision {
+  pub fn update(&mut self, autos: &[Auto], kinds: &Kinds) {
+    for vision in self.forces.values_mut() {
+      vision.visible.clear();
+    }
+
+    for auto in autos {
+      if !auto.flags.get(auto_alive) {
+        continue;
+      }
+      let sight = kinds.get_data(auto.kind).sight;
+      if sight <= 0 {
+        continue;
+      }
+      let vision = self.forces.entry(auto.force).or_default();
+      for dx in -sight..=sight {
+        for dy in -sight..=sight {
+          if dx * dx + dy * dy <= sight * sight {
+            let cell = (auto.parent, auto.loc + IVec2::new(dx, dy));
+            vision.visible.insert(cell);
+            vision.seen.insert(cell);
+          }
+        }
+      }
+    }
+  }
+
+  pub fn is_visible(&self, force: ForceNdx, cell: Cell) -> bool {
+    self.forces.get(&force).map_or(false, |vision| vision.visible.contains(&cell))
+  }
+
+  pub fn was_seen(&self, force: ForceNdx, cell: Cell) -> bool {
+    self.forces.get(&force).map_or(false, |vision| vision.seen.contains(&cell))
+  }
+}
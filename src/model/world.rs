@@ -2,9 +2,9 @@
 use bevy::{prelude::{IVec2, Resource, Plugin, App, ResMut, Res}, time::Time};
 use conniver::Val;
 
-use crate::model::{auto::{Auto, AutoNdx}, kind::{Kind, Kinds}, act::Action, pattern::{Pattern, Patterns}, slot::Slot};
+use crate::model::{auto::{Auto, AutoNdx}, kind::{Kind, Kinds}, act::{Action, CustomAction}, pattern::{Pattern, Patterns}, slot::Slot};
 
-use super::{force::Forces, auto::auto_action_finished};
+use super::{force::{Forces, ForceNdx, Relation}, auto::auto_action_finished, vision::Vision, event::WorldEvent, task::TaskBoard};
 
 #[derive(Resource)]
 pub struct World {
@@ -12,6 +12,19 @@ pub struct World {
   pub kinds: Kinds,
   pub patterns: Patterns,
   pub forces: Forces,
+  pub vision: Vision,
+  /// Events since the program space last took them.
+  pub events: Vec<WorldEvent>,
+  pub custom_actions: Vec<(String, CustomAction)>,
+  pub tasks: TaskBoard,
+  /// Whole ticks of simulation time so far, which script timers count in.
+  pub tick: u64,
+  /// Simulation time since the last whole tick.
+  pub tick_time: f64,
+  /// Time since conveyors last moved their items.
+  pub conveyor_time: f64,
+  /// The cells conveyors moved items from and to on their last tick, for drawing them in between.
+  pub conveyed: Vec<(AutoNdx, IVec2, IVec2)>,
 }
 
 impl World {
@@ -22,6 +35,14 @@ impl World {
       kinds,
       autos: vec![],
       forces: Forces::new_blank(),
+      vision: Vision::default(),
+      events: vec![],
+      custom_actions: vec![],
+      tasks: TaskBoard::default(),
+      tick: 0,
+      tick_time: 0.0,
+      conveyor_time: 0.0,
+      conveyed: vec![],
     };
     world.create_auto(Auto {
       kind: world.kinds.get("space"),
@@ -39,6 +60,14 @@ impl World {
       kinds,
       autos: vec![],
       forces: Forces::new_blank(),
+      vision: Vision::default(),
+      events: vec![],
+      custom_actions: vec![],
+      tasks: TaskBoard::default(),
+      tick: 0,
+      tick_time: 0.0,
+      conveyor_time: 0.0,
+      conveyed: vec![],
     };
     world.create_auto(Auto {
       kind: world.kinds.get("space"),
@@ -102,6 +131,7 @@ impl World {
       self.get_auto_mut(new.parent).children.push(result);
     }
     self.autos.push(new);
+    self.events.push(WorldEvent::AutoCreated(result));
     result
   }
 
@@ -124,6 +154,38 @@ impl World {
     auto.get_item(loc)
   }
 
+  /// Starts or ends reloading definitions, during which a kind's first definition replaces its old one
+  /// and its first pattern replaces its old patterns.
+  pub fn set_reloading(&mut self, reloading: bool) {
+    self.kinds.set_reloading(reloading);
+    self.patterns.set_reloading(reloading);
+  }
+
+  /// Sets a slot's stack, keeping it within the kind's max stack.
+  pub fn set_stack(&mut self, auto: AutoNdx, loc: IVec2, item: Kind, count: i32) {
+    let count = count.min(self.kinds.get_data(item).max_stack());
+    let auto = self.get_auto_mut(auto);
+    auto.set_stack(loc, item, count);
+  }
+
+  pub fn get_count(&self, auto: AutoNdx, loc: IVec2) -> i32 {
+    let auto = self.get_auto(auto);
+    auto.get_count(loc)
+  }
+
+  /// How many more of `item` fit in the slot, counting an empty slot as a fresh stack.
+  pub fn stack_room(&self, auto: AutoNdx, loc: IVec2, item: Kind) -> i32 {
+    let there = self.get_item(auto, loc);
+    let max = self.kinds.get_data(item).max_stack();
+    if there == Kind(0) {
+      max
+    } else if there == item {
+      (max - self.get_count(auto, loc)).max(0)
+    } else {
+      0
+    }
+  }
+
   #[cfg(test)]
   pub fn has_item(&self, auto: AutoNdx, loc: bevy::prelude::IVec2) -> bool {
     let auto = self.get_auto(auto);
@@ -147,6 +209,16 @@ impl World {
     auto.stall_message.clone()
   }
 
+  /// The program an auto runs whenever its proc is idle: its own, or else its kind's.
+  pub fn auto_program(&self, auto: AutoNdx) -> Val {
+    let auto = self.get_auto(auto);
+    if auto.program.is_nil() {
+      self.kinds.get_data(auto.kind).program.clone()
+    } else {
+      auto.program.clone()
+    }
+  }
+
   pub fn get_auto(&self, auto: AutoNdx) -> &Auto {
     &self.autos[auto.0]
   }
@@ -156,9 +228,16 @@ impl World {
   }
 
   pub fn update(&mut self, dur: f64) {
+    self.tick_time += dur;
+    while self.tick_time >= 1.0 {
+      self.tick_time -= 1.0;
+      self.tick += 1;
+    }
     for auto in self.auto_ndxes() {
       self.update_auto(auto, dur);
     }
+    self.update_conveyors(dur);
+    self.vision.update(&self.autos, &self.kinds);
   }
 
   pub fn update_auto(&mut self, ndx: AutoNdx, dur: f64) {
@@ -170,7 +249,13 @@ impl World {
         let action = auto.action;
         let stall_message = action.act(self, ndx);
         let auto = self.get_auto_mut(ndx);
-        auto.stall_message = stall_message;
+        let newly_stalled = stall_message.is_some() && stall_message != auto.stall_message;
+        auto.stall_message = stall_message.clone();
+        if let (true, Some(message)) = (newly_stalled, stall_message) {
+          // whatever it was doing for the task board, someone else can try
+          self.tasks.release_auto(ndx);
+          self.events.push(WorldEvent::ActionStalled(ndx, message));
+        }
         // let auto = self.get_auto(ndx);
         // if let Some(message) = &auto.stall_message {
         //   println!("{}: {}", self.kinds.get_data(auto.kind).name, message);
@@ -179,7 +264,29 @@ impl World {
     }
   }
 
+  /// Adds an action from outside the crate, replacing any with the same name. Returns its index for
+  /// `Action::Custom`.
+  pub fn add_custom_action(&mut self, name: &str, behaviour: CustomAction) -> usize {
+    if let Some(ndx) = self.custom_action(name) {
+      self.custom_actions[ndx].1 = behaviour;
+      ndx
+    } else {
+      self.custom_actions.push((name.to_string(), behaviour));
+      self.custom_actions.len() - 1
+    }
+  }
+
+  pub fn custom_action(&self, name: &str) -> Option<usize> {
+    self.custom_actions.iter().position(|(action, _)| action == name)
+  }
+
   pub fn finish_auto_action(&mut self, ndx: AutoNdx) {
+    self.idle_auto(ndx);
+    self.events.push(WorldEvent::ActionFinished(ndx));
+  }
+
+  /// Marks an auto as done with its action without it having finished anything, as when it's stopped.
+  pub fn idle_auto(&mut self, ndx: AutoNdx) {
     let auto = self.get_auto_mut(ndx);
     auto.flags.set(auto_action_finished, true);
     //auto.action = Action::Stop;
@@ -203,7 +310,7 @@ impl World {
       if auto.parent != parent_ndx {
         panic!("bad parent {} {}", auto.parent.0, parent_ndx.0);
       }
-      let dim = auto.dim;
+      let dim = auto.footprint();
       let loc = loc - auto.loc;
       if loc.x >= 0 && loc.x < dim.x && loc.y >= 0 && loc.y < dim.y {
         ndxes.push(Slot(ndx, loc));
@@ -219,6 +326,12 @@ impl World {
     auto.traction > ground.traction
   }
 
+  /// Whether an auto could move off where it stands, like a robot, rather than being built in place.
+  pub fn is_mobile(&self, auto: AutoNdx) -> bool {
+    let auto = self.get_auto(auto);
+    self.traction_valid(auto.parent, auto.kind, auto.loc)
+  }
+
   pub fn get_tile(&self, parent: AutoNdx, pos: IVec2) -> Kind {
     let parent = self.get_auto(parent);
     let ndx = parent.get_ndx(pos);
@@ -246,8 +359,13 @@ impl World {
     auto.items.clone()
   }
 
-  pub fn get_pattern(&self, kind: Kind, holding: &Vec<Kind>) -> Option<Pattern> {
-    self.patterns.get(kind, holding)
+  pub fn get_counts(&self, auto: AutoNdx) -> Vec<i32> {
+    let auto = self.get_auto(auto);
+    auto.counts.clone()
+  }
+
+  pub fn get_pattern(&self, kind: Kind, holding: &[Kind], counts: &[i32]) -> Option<Pattern> {
+    self.patterns.get_stacked(kind, holding, counts)
   }
 
   pub fn pick_place_target(&self, auto_ndx: AutoNdx, target_kind: Kind, item_kind: Kind) -> Option<Slot> {
@@ -266,6 +384,10 @@ impl World {
         if slot.0 == auto_ndx {
           continue;
         }
+        // only allies may take from, or add to, another auto's stacks
+        if !self.may_access(auto_ndx, slot.0) {
+          continue;
+        }
         let auto = self.get_auto(slot.0);
         if auto.kind.matches(target_kind) {
           let item_there = self.get_item(slot.0, slot.1);
@@ -290,6 +412,60 @@ impl World {
     
     result
   }
+
+  pub fn relation(&self, auto: AutoNdx, other: AutoNdx) -> Relation {
+    let auto = self.get_auto(auto);
+    let other = self.get_auto(other);
+    self.forces.relation(auto.force, other.force)
+  }
+
+  pub fn may_access(&self, auto: AutoNdx, other: AutoNdx) -> bool {
+    self.relation(auto, other) == Relation::Ally
+  }
+
+  /// Whether a force can see a cell right now. Forces always see into the autos they own.
+  #[cfg(test)]
+  pub fn cell_visible(&self, force: ForceNdx, space: AutoNdx, loc: IVec2) -> bool {
+    self.get_auto(space).force == force || self.vision.is_visible(force, (space, loc))
+  }
+
+  pub fn cell_seen(&self, force: ForceNdx, space: AutoNdx, loc: IVec2) -> bool {
+    self.get_auto(space).force == force || self.vision.was_seen(force, (space, loc))
+  }
+
+  /// Whether a force knows what is in a slot, either as a cell of the auto or of the ground it stands on.
+  pub fn slot_seen(&self, force: ForceNdx, auto: AutoNdx, loc: IVec2) -> bool {
+    let auto_data = self.get_auto(auto);
+    self.cell_seen(force, auto, loc) || self.cell_seen(force, auto_data.parent, auto_data.loc + loc)
+  }
+
+  pub fn auto_visible(&self, force: ForceNdx, auto: AutoNdx) -> bool {
+    let auto = self.get_auto(auto);
+    auto.force == force || self.vision.is_visible(force, (auto.parent, auto.loc))
+  }
+
+  pub fn in_bounds(&self, auto: AutoNdx, loc: IVec2) -> bool {
+    let dim = self.get_auto(auto).footprint();
+    loc.x >= 0 && loc.y >= 0 && loc.x < dim.x && loc.y < dim.y
+  }
+
+  /// The slots an auto can reach at a cell next to it: those of the autos there that it may access, or the
+  /// ground if there are none.
+  pub fn reach_slots(&self, auto_ndx: AutoNdx, loc: IVec2) -> Vec<Slot> {
+    let parent = self.get_auto(auto_ndx).parent;
+    let slots = self.get_slots(parent, loc);
+    if slots.is_empty() {
+      return if self.in_bounds(parent, loc) { vec![Slot(parent, loc)] } else { vec![] };
+    }
+    slots.into_iter().filter(|slot| slot.0 != auto_ndx && self.may_access(auto_ndx, slot.0)).collect()
+  }
+
+  /// Like `pick_place_target` for an empty slot, but prefers topping up a stack of the same item.
+  pub fn place_target(&self, auto_ndx: AutoNdx, target_kind: Kind, item_kind: Kind) -> Option<Slot> {
+    let stack = self.pick_place_target(auto_ndx, target_kind, item_kind)
+      .filter(|slot| self.stack_room(slot.0, slot.1, item_kind) > 0);
+    stack.or_else(|| self.pick_place_target(auto_ndx, target_kind, Kind(0)))
+  }
 }
 
 pub struct RS98WorldPlugin;
@@ -0,0 +1,294 @@
+use conniver::{Val, p, object::read_string};
+
+/// How a node did the last time it was ticked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+  Ready,
+  Running,
+  Success,
+  Failure,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NodeKind {
+  /// Runs its children in order until one fails.
+  Sequence,
+  /// Runs its children in order until one succeeds.
+  Selector,
+  /// Ticks every child, succeeding once all have and failing as soon as one does. Leaves still run one at a
+  /// time, since an auto only has one proc.
+  Parallel,
+  /// Runs its child again after a failure, up to this many more times.
+  Retry(u32),
+  /// An expression that succeeds if it's true.
+  Condition(Val),
+  /// An expression, usually a message like `(pick)` or `(goto 3 4)`, that succeeds unless it returns an
+  /// error or the auto stalls.
+  Action(Val),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+  pub kind: NodeKind,
+  pub children: Vec<usize>,
+}
+
+/// A behaviour tree being run by an auto, written like
+/// `(sequence (goto 3 4) (pick) (retry 2 (selector (condition (has-room)) (place)))`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Behaviour {
+  nodes: Vec<Node>,
+  status: Vec<Status>,
+  attempts: Vec<u32>,
+  /// The leaf whose program the auto is running.
+  leaf: Option<usize>,
+  leaf_result: Option<Status>,
+  /// How the last pass through the whole tree ended.
+  pub last: Option<Status>,
+}
+
+impl Behaviour {
+  pub fn parse(tree: &Val) -> Result<Behaviour, String> {
+    let mut behaviour = Behaviour {
+      nodes: vec![],
+      status: vec![],
+      attempts: vec![],
+      leaf: None,
+      leaf_result: None,
+      last: None,
+    };
+    behaviour.parse_node(tree)?;
+    behaviour.status = vec![Status::Ready; behaviour.nodes.len()];
+    behaviour.attempts = vec![0; behaviour.nodes.len()];
+    Ok(behaviour)
+  }
+
+  fn parse_node(&mut self, val: &Val) -> Result<usize, String> {
+    let bad = || format!("error: bad behaviour node {}", read_string(val));
+    let list = match val {
+      Val::List(list) if !list.is_empty() => list,
+      _ => return Err(bad()),
+    };
+    let head = match &list[0] {
+      Val::Sym(head) => head.as_str(),
+      _ => return Err(bad()),
+    };
+    let (kind, children) = match head {
+      "sequence" => (NodeKind::Sequence, &list[1..]),
+      "selector" => (NodeKind::Selector, &list[1..]),
+      "parallel" => (NodeKind::Parallel, &list[1..]),
+      "retry" => match (list.get(1), list.len()) {
+        (Some(Val::Num(times)), 3) => (NodeKind::Retry(times.max(0.0) as u32), &list[2..]),
+        _ => return Err(bad()),
+      },
+      "condition" if list.len() == 2 => (NodeKind::Condition(list[1].clone()), &list[..0]),
+      "condition" => return Err(bad()),
+      _ => (NodeKind::Action(val.clone()), &list[..0]),
+    };
+    let ndx = self.nodes.len();
+    self.nodes.push(Node { kind, children: vec![] });
+    for child in children {
+      let child = self.parse_node(child)?;
+      self.nodes[ndx].children.push(child);
+    }
+    Ok(ndx)
+  }
+
+  /// Advances the tree as far as it can go while the auto is idle, returning the program for the next leaf
+  /// to run, if there is one. A finished tree starts over on the next tick.
+  pub fn tick(&mut self) -> Option<Val> {
+    if self.leaf.is_some() && self.leaf_result.is_none() {
+      return None;
+    }
+    let status = self.tick_node(0);
+    if status == Status::Success || status == Status::Failure {
+      self.last = Some(status);
+      self.reset(0);
+      return None;
+    }
+    let leaf = self.leaf.filter(|_| self.leaf_result.is_none())?;
+    Some(match &self.nodes[leaf].kind {
+      NodeKind::Condition(expr) => {
+        let test = p("(behaviour-result (if x 'success 'failure))");
+        replace(&test, &[1, 1], expr.clone())
+      }
+      NodeKind::Action(expr) => Val::List(vec![Val::Sym("behaviour-result".to_string()), expr.clone()]),
+      _ => unreachable!("only leaves run"),
+    })
+  }
+
+  fn tick_node(&mut self, ndx: usize) -> Status {
+    if matches!(self.status[ndx], Status::Success | Status::Failure) {
+      return self.status[ndx];
+    }
+    let status = match self.nodes[ndx].kind {
+      NodeKind::Condition(_) | NodeKind::Action(_) => match self.leaf {
+        Some(leaf) if leaf == ndx => {
+          if let Some(result) = self.leaf_result.take() {
+            self.leaf = None;
+            result
+          } else {
+            Status::Running
+          }
+        }
+        Some(_) => Status::Running,
+        None => {
+          self.leaf = Some(ndx);
+          Status::Running
+        }
+      },
+      NodeKind::Sequence | NodeKind::Selector => {
+        let until = if self.nodes[ndx].kind == NodeKind::Sequence { Status::Failure } else { Status::Success };
+        let mut status = if until == Status::Failure { Status::Success } else { Status::Failure };
+        for i in 0..self.nodes[ndx].children.len() {
+          let child = self.tick_node(self.nodes[ndx].children[i]);
+          if child == Status::Running || child == until {
+            status = child;
+            break;
+          }
+        }
+        status
+      }
+      NodeKind::Parallel => {
+        let mut status = Status::Success;
+        for i in 0..self.nodes[ndx].children.len() {
+          match self.tick_node(self.nodes[ndx].children[i]) {
+            Status::Failure => {
+              status = Status::Failure;
+              break;
+            }
+            Status::Success => {}
+            _ => status = Status::Running,
+          }
+        }
+        status
+      }
+      NodeKind::Retry(times) => {
+        // a failed child starts over straight away, rather than waiting a tick
+        let child = self.nodes[ndx].children[0];
+        let mut status = self.tick_node(child);
+        while status == Status::Failure && self.attempts[ndx] < times {
+          self.attempts[ndx] += 1;
+          self.reset(child);
+          status = self.tick_node(child);
+        }
+        status
+      }
+    };
+    self.status[ndx] = status;
+    status
+  }
+
+  fn reset(&mut self, ndx: usize) {
+    self.status[ndx] = Status::Ready;
+    self.attempts[ndx] = 0;
+    for i in 0..self.nodes[ndx].children.len() {
+      self.reset(self.nodes[ndx].children[i]);
+    }
+  }
+
+  /// Called with what the running leaf's program ended with.
+  pub fn finish_leaf(&mut self, success: bool) {
+    if self.leaf.is_some() {
+      self.leaf_result = Some(if success { Status::Success } else { Status::Failure });
+    }
+  }
+
+  pub fn leaf_running(&self) -> bool {
+    self.leaf.is_some() && self.leaf_result.is_none()
+  }
+
+  /// The path from the root to the running leaf, like `sequence > retry 2 > (pick)`.
+  pub fn describe(&self) -> String {
+    let mut path = vec![];
+    if let Some(leaf) = self.leaf {
+      self.path_to(0, leaf, &mut path);
+    }
+    let last = match self.last {
+      Some(Status::Success) => ", last succeeded",
+      Some(Status::Failure) => ", last failed",
+      _ => "",
+    };
+    if path.is_empty() {
+      format!("idle{last}")
+    } else {
+      format!("{}{last}", path.join(" > "))
+    }
+  }
+
+  fn path_to(&self, ndx: usize, leaf: usize, path: &mut Vec<String>) -> bool {
+    path.push(self.label(ndx));
+    if ndx == leaf || self.nodes[ndx].children.iter().any(|child| self.path_to(*child, leaf, path)) {
+      return true;
+    }
+    path.pop();
+    false
+  }
+
+  fn label(&self, ndx: usize) -> String {
+    match &self.nodes[ndx].kind {
+      NodeKind::Sequence => "sequence".to_string(),
+      NodeKind::Selector => "selector".to_string(),
+      NodeKind::Parallel => "parallel".to_string(),
+      NodeKind::Retry(times) => format!("retry {times}"),
+      NodeKind::Condition(expr) => format!("condition {}", read_string(expr)),
+      NodeKind::Action(expr) => read_string(expr),
+    }
+  }
+}
+
+/// The tree in a program like `(behaviour (sequence ...))` or `(behaviour haul)`, if it is one.
+pub fn behaviour_tree(program: &Val) -> Option<Val> {
+  match program {
+    Val::List(list) if list.len() == 2 && list[0] == Val::Sym("behaviour".to_string()) => Some(list[1].clone()),
+    _ => None,
+  }
+}
+
+/// Whether a leaf's result counts as success: anything but `'failure`, an error, or no route.
+pub fn leaf_succeeded(result: &Val) -> bool {
+  match result {
+    Val::Sym(sym) => sym != "failure",
+    Val::String(text) => !(text.starts_with("error:") || text.starts_with("usage:") || text == "no route"),
+    _ => true,
+  }
+}
+
+fn replace(val: &Val, path: &[usize], with: Val) -> Val {
+  match (val, path.split_first()) {
+    (Val::List(list), Some((first, rest))) => {
+      let mut list = list.clone();
+      list[*first] = replace(&list[*first], rest, with);
+      Val::List(list)
+    }
+    _ => with,
+  }
+}
+
+#[test]
+fn test_behaviour() {
+  let tree = p("(sequence (pick) (retry 1 (selector (condition (full)) (place))) (produce))");
+  let mut behaviour = Behaviour::parse(&tree).unwrap();
+
+  assert_eq!(behaviour.tick(), Some(p("(behaviour-result (pick))")));
+  assert_eq!(behaviour.tick(), None);
+  behaviour.finish_leaf(true);
+  assert_eq!(behaviour.tick(), Some(p("(behaviour-result (if (full) 'success 'failure))")));
+  assert_eq!(behaviour.describe(), "sequence > retry 1 > selector > condition (full)");
+  behaviour.finish_leaf(false);
+  assert_eq!(behaviour.tick(), Some(p("(behaviour-result (place))")));
+  behaviour.finish_leaf(false);
+
+  // the retry starts the selector over
+  assert_eq!(behaviour.tick(), Some(p("(behaviour-result (if (full) 'success 'failure))")));
+  behaviour.finish_leaf(true);
+  assert_eq!(behaviour.tick(), Some(p("(behaviour-result (produce))")));
+  behaviour.finish_leaf(true);
+  assert_eq!(behaviour.tick(), None);
+  assert_eq!(behaviour.last, Some(Status::Success));
+  assert_eq!(behaviour.tick(), Some(p("(behaviour-result (pick))")));
+
+  assert!(Behaviour::parse(&p("(retry (pick))")).is_err());
+  assert!(leaf_succeeded(&Val::nil()));
+  assert!(!leaf_succeeded(&Val::String("no route".to_string())));
+}
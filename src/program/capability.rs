@@ -0,0 +1,58 @@
+/// What a script is allowed to do.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Capability {
+  /// Loader and admin scripts, which build the world and run the game.
+  Admin,
+  /// Robot scripts, which may only change their own force's autos, and only read what their force can see.
+  #[default]
+  Robot,
+}
+
+/// Messages that change the rules of the world rather than act within it.
+const ADMIN_MESSAGES: &[&str] = &[
+  "define-kind",
+  "define-pattern",
+  "begin-reload",
+  "end-reload",
+  "define-behaviour",
+  "define-force",
+  "set-relation",
+  "create-auto",
+  "access",
+  "set-priority",
+  "grant",
+  "quit",
+  "on-quit",
+  "add-module-path",
+];
+
+impl Capability {
+  pub fn from_str(name: &str) -> Option<Self> {
+    match name {
+      "admin" => Some(Capability::Admin),
+      "robot" => Some(Capability::Robot),
+      _ => None,
+    }
+  }
+
+  pub fn to_str(&self) -> &'static str {
+    match self {
+      Capability::Admin => "admin",
+      Capability::Robot => "robot",
+    }
+  }
+
+  pub fn allows(&self, message: &str) -> bool {
+    *self == Capability::Admin || !ADMIN_MESSAGES.contains(&message)
+  }
+}
+
+#[test]
+fn test_capability() {
+  assert!(Capability::Admin.allows("create-auto"));
+  assert!(!Capability::Robot.allows("create-auto"));
+  assert!(!Capability::Robot.allows("quit"));
+  assert!(Capability::Robot.allows("item-at"));
+  assert_eq!(Capability::from_str("robot"), Some(Capability::Robot));
+  assert_eq!(Capability::from_str("root"), None);
+}
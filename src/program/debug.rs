@@ -0,0 +1,79 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::model::auto::AutoNdx;
+
+/// Pauses autos at messages, so a script can be walked through one message at a time.
+#[derive(Default)]
+pub struct Debugger {
+  paused: HashSet<AutoNdx>,
+  breakpoints: HashMap<AutoNdx, HashSet<String>>,
+  /// Autos let through to their next message; true once the current one has finished.
+  stepping: HashMap<AutoNdx, bool>,
+  /// Autos that resumed at a breakpoint, which shouldn't stop at it again straight away.
+  skip_break: HashSet<AutoNdx>,
+}
+
+impl Debugger {
+  pub fn is_paused(&self, auto: AutoNdx) -> bool {
+    self.paused.contains(&auto)
+  }
+
+  pub fn pause(&mut self, auto: AutoNdx) {
+    self.stepping.remove(&auto);
+    self.paused.insert(auto);
+  }
+
+  pub fn resume(&mut self, auto: AutoNdx) {
+    self.stepping.remove(&auto);
+    if self.paused.remove(&auto) {
+      self.skip_break.insert(auto);
+    }
+  }
+
+  /// Lets a paused auto finish its current message and run up to the next one.
+  pub fn step(&mut self, auto: AutoNdx) {
+    if self.paused.remove(&auto) {
+      self.stepping.insert(auto, false);
+      self.skip_break.insert(auto);
+    }
+  }
+
+  pub fn set_breakpoint(&mut self, auto: AutoNdx, message: &str) {
+    self.breakpoints.entry(auto).or_default().insert(message.to_string());
+  }
+
+  pub fn clear_breakpoint(&mut self, auto: AutoNdx, message: &str) {
+    if let Some(breakpoints) = self.breakpoints.get_mut(&auto) {
+      breakpoints.remove(message);
+    }
+  }
+
+  pub fn breakpoints(&self, auto: AutoNdx) -> Vec<String> {
+    let mut result = self.breakpoints.get(&auto).map(|breakpoints| breakpoints.iter().cloned().collect::<Vec<String>>()).unwrap_or_default();
+    result.sort();
+    result
+  }
+
+  /// Whether a pending message may be handled, pausing the auto if it hit a breakpoint or finished a step.
+  pub fn allow(&mut self, auto: AutoNdx, message: &str) -> bool {
+    if self.paused.contains(&auto) {
+      return false;
+    }
+    let stepped = self.stepping.get(&auto) == Some(&true);
+    let hit = !self.skip_break.contains(&auto)
+      && self.breakpoints.get(&auto).is_some_and(|breakpoints| breakpoints.contains(message));
+    if stepped || hit {
+      self.pause(auto);
+      return false;
+    }
+    true
+  }
+
+  /// Called when a message has been handled and returned to the script.
+  pub fn handled(&mut self, auto: AutoNdx) {
+    if let Some(finished) = self.stepping.get_mut(&auto) {
+      *finished = true;
+    }
+    self.skip_break.remove(&auto);
+  }
+}
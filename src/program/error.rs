@@ -0,0 +1,53 @@
+use conniver::{Val, object::read_string};
+
+/// How many errors each auto remembers.
+pub const ERROR_LOG_SIZE: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptError {
+  pub message: String,
+  pub args: Vec<Val>,
+  pub error: String,
+  pub tick: u64,
+}
+
+impl ScriptError {
+  /// Handlers report bad arguments by returning a usage string instead of a result.
+  pub fn from_result(message: &[Val], result: &Val, tick: u64) -> Option<ScriptError> {
+    if let Val::String(text) = result {
+      if text.starts_with("usage:") || text.starts_with("error:") {
+        let name = if let Some(Val::Sym(name)) = message.get(0) {
+          name.clone()
+        } else {
+          String::new()
+        };
+        return Some(ScriptError {
+          message: name,
+          args: message.get(1..).unwrap_or_default().to_vec(),
+          error: text.clone(),
+          tick,
+        });
+      }
+    }
+    None
+  }
+
+  /// Errors raised by the interpreter itself, like an unbound symbol or a bad call, stop the proc
+  /// rather than coming back from a handler.
+  pub fn from_interpreter(error: &Val, tick: u64) -> ScriptError {
+    let error = match error {
+      Val::String(text) => text.clone(),
+      _ => read_string(error),
+    };
+    ScriptError {
+      message: "eval".to_string(),
+      args: vec![],
+      error,
+      tick,
+    }
+  }
+
+  pub fn describe(&self) -> String {
+    format!("{} (tick {}): {}", self.message, self.tick, self.error)
+  }
+}
@@ -1,100 +1,97 @@
 use std::collections::HashMap;
 
-use bevy::prelude::IVec2;
-use conniver::{Val, object::read_string};
+use conniver::{Val, read_object, object::read_string};
 
-use crate::model::{auto::{AutoNdx, auto_action_finished}, world::World, act::Action, kind::Kind, dir::Dir, pattern::Pattern, route::route};
+use crate::model::{auto::{AutoNdx, auto_action_finished, read_filter}, world::World, act::Action, dir::Dir, kind::Kind, pattern::Pattern, route::route, force::Relation, event::WorldEvent, slot::Slot, task::TaskKind};
 
-use super::program::ProgramSpace;
+use super::{behaviour::{Behaviour, behaviour_tree, leaf_succeeded}, program::ProgramSpace, capability::Capability, signature::{Args, Signature}};
 
-pub type MessageHandler = fn(Vec<Val>, &mut ProgramSpace, &mut World, AutoNdx) -> Option<Val>;
+pub type MessageHandler = fn(Args, &mut ProgramSpace, &mut World, AutoNdx) -> Option<Val>;
 
-pub fn get_message_handlers() -> HashMap<String, MessageHandler> {
-  let mut handlers = HashMap::<String, MessageHandler>::new();
+/// A message scripts can send, with the signature its arguments are checked against.
+pub struct Message {
+  pub signature: Signature,
+  pub handler: MessageHandler,
+}
+
+fn register(handlers: &mut HashMap<String, Message>, spec: &str, handler: MessageHandler) {
+  let signature = Signature::parse(spec);
+  handlers.insert(signature.name.clone(), Message { signature, handler });
+}
 
-  handlers.insert("item-at".to_string(), |args, _, world, _| {
-    if args.len() < 4 {
-      return Some(Val::String("usage: (item-at auto x y)".to_owned()));
+pub fn get_message_handlers() -> HashMap<String, Message> {
+  let mut handlers = HashMap::<String, Message>::new();
+
+  register(&mut handlers, "item-at auto:auto loc:ivec2", |args, _, world, me| {
+    let (auto, pos) = (args.auto(0), args.ivec2(1));
+    let force = world.get_auto(me).force;
+    if !world.slot_seen(force, auto, pos) {
+      return Some(Val::nil());
     }
-    let auto = if let Val::Num(auto) = &args[1] {
-      AutoNdx(*auto as usize)
-    } else {
-      return Some(Val::String("usage: (item-at auto x y)".to_owned()));
-    };
-    let x = if let Val::Num(x) = args[2] {
-      x as i32
-    } else {
-      return Some(Val::String("usage: (item-at auto x y)".to_owned()));
-    };
-    let y = if let Val::Num(y) = args[3] {
-      y as i32
-    } else {
-      return Some(Val::String("usage: (item-at auto x y)".to_owned()));
-    };
-    let pos = IVec2::new(x, y);
     let item = world.get_item(auto, pos);
     let item_name = world.kinds.get_data(item).name.clone();
-    Some(Val::String(item_name))
+    Some(Val::List(vec![Val::String(item_name), Val::Num(world.get_count(auto, pos) as f32)]))
   });
 
-  handlers.insert("set-item".to_string(), |args, _, world, _| {
-    if args.len() < 5 {
-      return Some(Val::String("usage: (set-item auto x y kind)".to_owned()));
+  register(&mut handlers, "set-item auto:auto loc:ivec2 kind:kind [count:int]", |args, program, world, me| {
+    let (auto, pos, kind) = (args.auto(0), args.ivec2(1), args.kind(2));
+    let count = args.opt_int(3).unwrap_or(1);
+    if !program.may_modify(world, me, auto) {
+      return Some(Val::String(format!("error: auto {} is not ours", auto.0)));
     }
-    let auto = if let Val::Num(auto) = &args[1] {
-      AutoNdx(*auto as usize)
-    } else {
-      return Some(Val::String("usage: (set-item auto x y kind)".to_owned()));
-    };
-    let x = if let Val::Num(x) = args[2] {
-      x as i32
-    } else {
-      return Some(Val::String("usage: (set-item auto x y kind)".to_owned()));
-    };
-    let y = if let Val::Num(y) = args[3] {
-      y as i32
-    } else {
-      return Some(Val::String("usage: (set-item auto x y kind)".to_owned()));
-    };
-    let pos = IVec2::new(x, y);
-    let kind = if let Val::Sym(kind) = &args[4] {
-      world.kinds.get(kind)
-    } else {
-      return Some(Val::String("usage: (set-item auto x y kind)".to_owned()));
-    };
-    world.set_item(auto, pos, kind);
+    world.set_stack(auto, pos, kind, count);
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "add-item auto:auto loc:ivec2 kind:kind", |args, program, world, me| {
+    let (auto, pos, kind) = (args.auto(0), args.ivec2(1), args.kind(2));
+    if !program.may_modify(world, me, auto) {
+      return Some(Val::String(format!("error: auto {} is not ours", auto.0)));
+    }
+    // a full or mismatched slot is left alone
+    if world.stack_room(auto, pos, kind) > 0 {
+      let count = world.get_count(auto, pos);
+      world.set_stack(auto, pos, kind, count + 1);
+    }
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "print line:any...", |args, program, _, _| {
+    program.print(args.rest(0).iter().map(read_string).collect::<Vec<String>>().join(""));
     Some(Val::nil())
   });
 
-  handlers.insert("print".to_string(), |args, _, _, _| {
-    println!("{}", args[1..].iter().map(read_string).collect::<Vec<String>>().join(""));
+  register(&mut handlers, "console-return [result:any]", |args, program, _, auto| {
+    let result = read_string(&args.val(0));
+    program.print(format!("{}> {result}", auto.0));
     Some(Val::nil())
   });
 
-  handlers.insert("step".to_string(), |args, _, world, auto| {
-    if args.len() < 2 {
-      return Some(Val::String("usage: (step dir)".to_owned()));
+  register(&mut handlers, "step dir:dir", |args, _, world, auto| {
+    action_handler(world, auto, Action::Step(args.dir(0)))
+  });
+
+  register(&mut handlers, "rotate dir:dir", |args, _, world, auto| {
+    action_handler(world, auto, Action::Rotate(args.dir(0)))
+  });
+
+  register(&mut handlers, "facing [auto:auto]", |args, program, world, me| {
+    let auto = args.opt_auto(0).unwrap_or(me);
+    let (force, other) = (world.get_auto(me).force, world.get_auto(auto));
+    if !program.may_modify(world, me, auto) && !world.cell_seen(force, other.parent, other.loc) {
+      return Some(Val::nil());
     }
-    let dir = read_string(&args[1]);
-    let dir = Dir::from_str(&dir);
-    action_handler(world, auto, Action::Step(dir))
+    Some(Val::Sym(world.get_auto(auto).facing.to_str().to_string()))
   });
 
-  handlers.insert("route".to_string(), |args, _, world, auto| {
-    if args.len() < 3 {
-      return Some(Val::String("usage: (route auto x y)".to_owned()));
+  register(&mut handlers, "route loc:ivec2", |args, _, world, auto| {
+    let dest = args.ivec2(0);
+
+    // no routing into the unknown
+    let auto_data = world.get_auto(auto);
+    if !world.cell_seen(auto_data.force, auto_data.parent, dest) {
+      return Some(Val::String("no route".to_owned()));
     }
-    let x = if let Val::Num(x) = args[1] {
-      x as i32
-    } else {
-      return Some(Val::String("usage: (route auto x y)".to_owned()));
-    };
-    let y = if let Val::Num(y) = args[2] {
-      y as i32
-    } else {
-      return Some(Val::String("usage: (route auto x y)".to_owned()));
-    };
-    let dest = IVec2::new(x, y);
 
     let route_found = route(world, auto, dest);
     if let Some(route_found) = route_found {
@@ -106,79 +103,406 @@ pub fn get_message_handlers() -> HashMap<String, MessageHandler> {
     }
   });
 
-  handlers.insert("stop".to_string(), |_, _, world, auto| {
+  register(&mut handlers, "set-program program:any [auto:auto] [mode:once|always]", |args, program, world, me| {
+    let auto = args.opt_auto(1).unwrap_or(me);
+    if !program.may_modify(world, me, auto) {
+      return Some(Val::String(format!("error: auto {} is not ours", auto.0)));
+    }
+    // always also makes it the program the auto goes back to whenever it's idle, which a behaviour tree
+    // needs to get past its first leaf
+    if args.opt_name(2) == Some("always") || behaviour_tree(&args.val(0)).is_some() {
+      world.get_auto_mut(auto).program = args.val(0);
+    }
+    program.replace_program(auto, args.val(0));
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "clear-program [auto:auto]", |args, program, world, me| {
+    let auto = args.opt_auto(0).unwrap_or(me);
+    if !program.may_modify(world, me, auto) {
+      return Some(Val::String(format!("error: auto {} is not ours", auto.0)));
+    }
+    world.get_auto_mut(auto).program = Val::nil();
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "get-program [auto:auto]", |args, program, world, me| {
+    let auto = args.opt_auto(0).unwrap_or(me);
+    if !program.may_modify(world, me, auto) {
+      return Some(Val::String(format!("error: auto {} is not ours", auto.0)));
+    }
+    Some(world.auto_program(auto))
+  });
+
+  register(&mut handlers, "stop", |_, _, world, auto| {
     action_handler(world, auto, Action::Stop)
   });
 
-  handlers.insert("pick".to_string(), |_, _, world, auto| {
+  register(&mut handlers, "pick", |_, _, world, auto| {
     action_handler(world, auto, Action::Pick(Kind(1), Kind(1)))
   });
 
-  handlers.insert("place".to_string(), |_, _, world, auto| {
+  register(&mut handlers, "place", |_, _, world, auto| {
     action_handler(world, auto, Action::Place(Kind(1)))
   });
 
-  handlers.insert("produce".to_string(), |_, _, world, auto| {
+  register(&mut handlers, "produce", |_, _, world, auto| {
     action_handler(world, auto, Action::Produce)
   });
 
-  handlers.insert("define-kind".to_string(), |args, _, world, _| {
-    if args.len() < 2 {
-      return Some(Val::String("usage: (define-kind name (prop x) (prop y) ...)".to_owned()));
+  register(&mut handlers, "transfer", |_, _, world, auto| {
+    action_handler(world, auto, Action::Transfer)
+  });
+
+  register(&mut handlers, "set-filter auto:auto kinds:any...", |args, program, world, me| {
+    let auto = args.auto(0);
+    if !program.may_modify(world, me, auto) {
+      return Some(Val::String(format!("error: auto {} is not ours", auto.0)));
     }
-    let name = if let Val::Sym(name) = &args[1] {
-      name
-    } else {
-      return Some(Val::String("usage: (define-kind name (prop x) (prop y) ...)".to_owned()));
-    };
-    let props = Val::List(args[2..].to_vec());
-    world.kinds.set_by_val(name, props);
+    match read_filter(&Val::List(args.rest(1).to_vec()), &world.kinds) {
+      Ok(filter) => {
+        world.get_auto_mut(auto).filter = filter;
+        Some(Val::nil())
+      }
+      Err(name) => Some(Val::String(format!("error: unknown kind {name}"))),
+    }
+  });
+
+  register(&mut handlers, "define-kind name:name props:any...", |args, _, world, _| {
+    let props = Val::List(args.rest(1).to_vec());
+    world.kinds.set_by_val(args.name(0), props);
     Some(Val::nil())
   });
 
-  handlers.insert("define-pattern".to_string(), |args, _, world, _| {
-    if args.len() < 2 {
-      return Some(Val::String("usage: (define-pattern (for ...) (in ...) (out ...) ...)".to_owned()));
-    }
+  register(&mut handlers, "kind-prop kind:kind key:name", |args, _, world, _| {
+    Some(world.kinds.prop(args.kind(0), args.name(1)))
+  });
 
-    let args = Val::List(args[1..].to_vec());
-    let pattern = Pattern::from_val(&args, world);
+  register(&mut handlers, "define-pattern props:any...", |args, _, world, _| {
+    let props = Val::List(args.rest(0).to_vec());
+    let pattern = Pattern::from_val(&props, world);
     world.patterns.add(pattern);
     Some(Val::nil())
   });
 
-  handlers.insert("create-auto".to_string(), |args, program, world, _| {
-    if args.is_empty() {
-      return Some(Val::String("usage: (create-auto (name x) ...)".to_owned()));
+  register(&mut handlers, "begin-reload", |_, _, world, _| {
+    world.set_reloading(true);
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "end-reload", |_, _, world, _| {
+    world.set_reloading(false);
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "define-force name:name", |args, _, world, _| {
+    world.forces.define(args.name(0));
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "set-relation force:name other:name relation:ally|neutral|hostile", |args, _, world, _| {
+    let a = world.forces.get(args.name(0));
+    let b = world.forces.get(args.name(1));
+    let relation = Relation::from_str(args.name(2)).unwrap_or_default();
+    world.forces.set_relation(a, b, relation);
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "relation auto:auto", |args, _, world, auto| {
+    Some(Val::Sym(world.relation(auto, args.auto(0)).to_str().to_string()))
+  });
+
+  register(&mut handlers, "fire auto:auto", |args, _, world, auto| {
+    action_handler(world, auto, Action::Fire(args.auto(0)))
+  });
+
+  register(&mut handlers, "create-auto props:any...", |args, program, world, _| {
+    let props = Val::List(args.rest(0).to_vec());
+    let mut error = None;
+    read_object(&props, |key, val| {
+      let problem = match key {
+        "filter" => read_filter(val, &world.kinds).err().map(|name| format!("error: unknown kind {name}")),
+        "facing" if Dir::from_str(&read_string(val)) == Dir::None => Some(format!("error: unknown direction {}", read_string(val))),
+        _ => None,
+      };
+      error = error.take().or(problem);
+    });
+    if let Some(error) = error {
+      return Some(Val::String(error));
     }
-    let args = Val::List(args[1..].to_vec());
-    let auto = world.create_auto_from_val(args);
+    let auto = world.create_auto_from_val(props);
     program.init_auto(auto, world);
     Some(Val::Num(auto.0 as f32))
   });
 
-  handlers.insert("access".to_string(), |args, program, _, _| {
-    if args.is_empty() {
-      return Some(Val::String("usage: (access auto)".to_owned()));
+  register(&mut handlers, "access auto:auto", |args, program, _, _| {
+    program.access = args.auto(0);
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "set-priority auto:auto priority:int", |args, program, _, _| {
+    program.scheduler.set_priority(args.auto(0), args.int(1));
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "budget-report", |_, program, _, _| {
+    let report = program.scheduler.top_usage(10).into_iter().map(|(auto, used)| {
+      Val::List(vec![Val::Num(auto.0 as f32), Val::Num(used as f32)])
+    }).collect();
+    Some(Val::List(report))
+  });
+
+  register(&mut handlers, "send auto:auto message:any", |args, program, _, auto| {
+    let target = args.auto(0);
+    if !program.send(auto, target, args.val(1)) {
+      return Some(Val::String(format!("error: mailbox of auto {} is full", target.0)));
     }
-    let auto = if let Val::Num(auto) = &args[1] {
-      AutoNdx(*auto as usize)
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "receive", |_, program, _, auto| {
+    // blocks until something arrives
+    program.receive(auto).map(|(from, message)| {
+      Val::List(vec![Val::Num(from.0 as f32), message])
+    })
+  });
+
+  register(&mut handlers, "on-message [handler:name]", |args, program, _, auto| {
+    program.set_mail_handler(auto, args.opt_name(0).map(str::to_string));
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "subscribe event:name", |args, program, _, auto| {
+    let event = args.name(0);
+    if !WorldEvent::is_known(event) {
+      return Some(Val::String(format!("error: unknown event {event}")));
+    }
+    program.subscribe(auto, event);
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "unsubscribe event:name", |args, program, _, auto| {
+    program.unsubscribe(auto, args.name(0));
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "now", |_, program, _, _| {
+    Some(Val::Num(program.tick as f32))
+  });
+
+  register(&mut handlers, "sleep ticks:int", |args, program, _, auto| {
+    // blocks until the tick comes around
+    let tick = program.tick;
+    if program.timers.sleep(auto, tick, args.int(0).max(0) as u64) {
+      Some(Val::nil())
     } else {
-      return Some(Val::String("usage: (access auto)".to_owned()));
+      None
+    }
+  });
+
+  register(&mut handlers, "after ticks:int expr:any", |args, program, _, auto| {
+    let due = program.tick + args.int(0).max(0) as u64;
+    let id = program.timers.add(auto, due, None, args.val(1));
+    Some(Val::Num(id as f32))
+  });
+
+  register(&mut handlers, "every ticks:int expr:any", |args, program, _, auto| {
+    let ticks = args.int(0).max(1) as u64;
+    let id = program.timers.add(auto, program.tick + ticks, Some(ticks), args.val(1));
+    Some(Val::Num(id as f32))
+  });
+
+  register(&mut handlers, "cancel-timer id:int", |args, program, _, auto| {
+    let id = args.int(0);
+    if program.timers.cancel(auto, id.max(0) as u64) {
+      Some(Val::nil())
+    } else {
+      Some(Val::String(format!("error: no timer {id}")))
+    }
+  });
+
+  register(&mut handlers, "last-error", |_, program, _, auto| {
+    if let Some(error) = program.last_error(auto) {
+      Some(Val::String(error.describe()))
+    } else {
+      Some(Val::nil())
+    }
+  });
+
+  register(&mut handlers, "debug auto:auto command:pause|resume|step|where|break|unbreak|breaks|var [arg:name]", |args, program, world, me| {
+    // anyone may debug their own force's autos, so the console works on whichever robot it's accessing
+    if !program.may_modify(world, me, args.auto(0)) {
+      return Some(Val::String(format!("error: auto {} is not ours", args.auto(0).0)));
+    }
+    match program.debug_command(args.auto(0), args.name(1), args.opt_name(2)) {
+      Ok(result) => Some(Val::String(result)),
+      Err(error) => Some(Val::String(error)),
+    }
+  });
+
+  register(&mut handlers, "grant auto:auto capability:admin|robot", |args, program, _, _| {
+    program.set_capability(args.auto(0), Capability::from_str(args.name(1)).unwrap_or_default());
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "quit", |_, program, _, _| {
+    // the app exits once any on-quit script is done
+    program.request_quit();
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "on-quit [expr:any]", |args, program, _, _| {
+    program.on_quit = args.opt_val(0).filter(|expr| !expr.is_nil());
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "help [message:name]", |args, program, _, _| {
+    if let Some(name) = args.opt_name(0) {
+      return match program.signature(name) {
+        Some(signature) => Some(Val::String(signature.usage())),
+        None => Some(Val::String(format!("error: unknown message {name}"))),
+      };
+    }
+    Some(Val::List(program.signatures().iter().map(|signature| signature.to_val()).collect()))
+  });
+
+  register(&mut handlers, "define-behaviour name:name tree:any", |args, program, _, _| {
+    // checked now, so a typo shows up here rather than on every auto running it
+    if let Err(error) = Behaviour::parse(&args.val(1)) {
+      return Some(Val::String(error));
+    }
+    program.named_behaviours.insert(args.name(0).to_string(), args.val(1));
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "behaviour-result [result:any]", |args, program, _, auto| {
+    program.finish_behaviour_leaf(auto, leaf_succeeded(&args.val(0)));
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "behaviour-status [auto:auto]", |args, program, world, me| {
+    let auto = args.opt_auto(0).unwrap_or(me);
+    if !program.may_modify(world, me, auto) {
+      return Some(Val::String(format!("error: auto {} is not ours", auto.0)));
+    }
+    Some(program.behaviour_status(auto).map_or_else(Val::nil, Val::String))
+  });
+
+  register(&mut handlers, "post-task kind:supply|collect auto:auto loc:ivec2 item:kind", |args, program, world, me| {
+    let (auto, loc, item) = (args.auto(1), args.ivec2(2), args.kind(3));
+    if !program.may_modify(world, me, auto) {
+      return Some(Val::String(format!("error: auto {} is not ours", auto.0)));
+    }
+    let dim = world.get_auto(auto).footprint();
+    if loc.x < 0 || loc.y < 0 || loc.x >= dim.x || loc.y >= dim.y {
+      return Some(Val::String(format!("error: auto {} has no slot {} {}", auto.0, loc.x, loc.y)));
+    }
+    let kind = TaskKind::from_str(args.name(0)).unwrap_or(TaskKind::Supply);
+    let force = world.get_auto(auto).force;
+    Some(Val::Num(world.tasks.post(kind, force, Slot(auto, loc), item) as f32))
+  });
+
+  register(&mut handlers, "tasks", |_, _, world, me| {
+    let force = world.get_auto(me).force;
+    let tasks = world.tasks.tasks.iter().filter(|task| task.force == force).map(|task| {
+      Val::List(vec![
+        Val::Num(task.id as f32),
+        Val::Sym(task.kind.to_str().to_string()),
+        Val::Num(task.slot.0.0 as f32),
+        Val::Num(task.slot.1.x as f32),
+        Val::Num(task.slot.1.y as f32),
+        Val::Sym(world.kinds.name(task.item)),
+        task.claimed.map_or_else(Val::nil, |auto| Val::Num(auto.0 as f32)),
+      ])
+    }).collect();
+    Some(Val::List(tasks))
+  });
+
+  register(&mut handlers, "claim-task", |_, _, world, me| {
+    Some(world.claim_task(me).map_or_else(Val::nil, |id| Val::Num(id as f32)))
+  });
+
+  register(&mut handlers, "release-task id:int", |args, _, world, me| {
+    let id = args.int(0).max(0) as u64;
+    if world.tasks.get(id).map(|task| task.claimed) != Some(Some(me)) {
+      return Some(Val::String(format!("error: task {id} is not ours")));
+    }
+    world.tasks.release(id);
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "complete-task id:int", |args, program, world, me| {
+    let id = args.int(0).max(0) as u64;
+    // the robot that claimed it, or whoever runs the machine that posted it
+    let allowed = world.tasks.get(id).is_some_and(|task| task.claimed == Some(me) || program.may_modify(world, me, task.slot.0));
+    if !allowed {
+      return Some(Val::String(format!("error: task {id} is not ours")));
+    }
+    world.tasks.remove(id);
+    Some(Val::nil())
+  });
+
+  register(&mut handlers, "work-task", |_, program, world, me| {
+    // blocks until a task is done or given up on; nil if there was nothing to do
+    let id = match world.tasks.claimed_by(me).map(|task| task.id) {
+      Some(id) => id,
+      None => {
+        if let Some(id) = program.working.remove(&me) {
+          return Some(Val::String(format!("error: task {id} was released")));
+        }
+        match world.claim_task(me) {
+          Some(id) => id,
+          None => return Some(Val::nil()),
+        }
+      }
     };
-    program.access = auto;
+    program.working.insert(me, id);
+    match world.work_task(me, id) {
+      Ok(false) => None,
+      Ok(true) => {
+        program.working.remove(&me);
+        world.tasks.remove(id);
+        Some(Val::Num(id as f32))
+      }
+      Err(error) => {
+        program.working.remove(&me);
+        world.tasks.release(id);
+        Some(Val::String(error))
+      }
+    }
+  });
+
+  register(&mut handlers, "require module:name [prefix:name]", |args, program, _, auto| {
+    // blocks while the module's code runs
+    let tick = program.tick;
+    match program.modules.require(auto, args.name(0), args.opt_name(1), tick)? {
+      Ok(()) => Some(Val::nil()),
+      Err(error) => Some(Val::String(error)),
+    }
+  });
+
+  register(&mut handlers, "module-loaded module:name [prefix:name]", |args, program, _, auto| {
+    if program.modules.loaded(auto, args.name(0), args.opt_name(1)) {
+      Some(Val::nil())
+    } else {
+      Some(Val::String("error: module-loaded is only for require".to_string()))
+    }
+  });
+
+  register(&mut handlers, "provide names:any...", |_, _, _, _| {
+    // only means something to require, which takes it out of the module
     Some(Val::nil())
   });
 
-  handlers.insert("quit".to_string(), |_, _, _, _| {
-    // quit the application
-    std::process::exit(0);
+  register(&mut handlers, "add-module-path path:name", |args, program, _, _| {
+    program.modules.add_path(args.name(0));
+    Some(Val::nil())
   });
 
   handlers
 }
 
-fn action_handler(world: &mut World, auto: AutoNdx, generator: Action) -> Option<Val> {
+pub fn action_handler(world: &mut World, auto: AutoNdx, generator: Action) -> Option<Val> {
   let action = world.get_auto_action(auto);
   if action != generator {
     world.set_auto_action(auto, generator);
@@ -1,5 +1,17 @@
 
+pub mod behaviour;
+pub mod capability;
+pub mod debug;
+pub mod error;
 pub mod message;
+pub mod module;
 #[allow(clippy::module_inception)]
 pub mod program;
+pub mod registry;
+pub mod reload;
+pub mod remote;
+pub mod repl;
+pub mod schedule;
+pub mod signature;
+pub mod timer;
 pub mod test;
\ No newline at end of file
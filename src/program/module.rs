@@ -0,0 +1,216 @@
+use std::{collections::{HashMap, HashSet}, path::Path};
+
+use conniver::{Val, val::p_all};
+
+use crate::model::auto::AutoNdx;
+
+/// Where `require` looks for modules, in order.
+pub const DEFAULT_MODULE_PATHS: &[&str] = &["assets/cnvr/lib", "assets/cnvr"];
+
+/// How far along a `require` is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Require {
+  /// The module's code is waiting to be started next frame.
+  Queued,
+  /// The module's code was started on this tick.
+  Started(u64),
+  Done,
+}
+
+/// `.cnvr` libraries loaded with `require`. A module's top-level definitions are renamed to
+/// `module/name` so they can't clobber the requiring script's, and the names it lists in `(provide ...)`
+/// are bound under their plain names, or as `prefix/name` when required with a prefix.
+pub struct Modules {
+  pub paths: Vec<String>,
+  /// Each module's exported names, by module name.
+  exports: HashMap<String, Vec<String>>,
+  /// Modules whose bodies have run on an auto.
+  loaded: HashSet<(AutoNdx, String)>,
+  /// Requires that have been answered or are still running.
+  bound: HashMap<(AutoNdx, String, Option<String>), Require>,
+  /// Module code to run next frame, since the requiring auto is waiting on the message.
+  pending: Vec<(AutoNdx, Val)>,
+}
+
+impl Default for Modules {
+  fn default() -> Self {
+    Self {
+      paths: DEFAULT_MODULE_PATHS.iter().map(|path| path.to_string()).collect(),
+      exports: HashMap::new(),
+      loaded: HashSet::new(),
+      bound: HashMap::new(),
+      pending: Vec::new(),
+    }
+  }
+}
+
+impl Modules {
+  /// Handles `(require name [prefix])`. Returns None while the module is still loading.
+  pub fn require(&mut self, auto: AutoNdx, name: &str, prefix: Option<&str>, tick: u64) -> Option<Result<(), String>> {
+    let key = (auto, name.to_string(), prefix.map(str::to_string));
+    match self.bound.get(&key) {
+      Some(Require::Done) => return Some(Ok(())),
+      Some(Require::Started(started)) if tick > *started => {
+        // the auto is back at its require, so the module's code stopped before reaching its end
+        self.bound.remove(&key);
+        return Some(Err(format!("error: module {name} failed to load")));
+      }
+      Some(_) => return None,
+      None => {}
+    }
+
+    let mut forms = vec![];
+    if !self.loaded.contains(&(auto, name.to_string())) {
+      let source = match self.read(name) {
+        Ok(source) => source,
+        Err(error) => return Some(Err(error)),
+      };
+      let (body, exports) = module_body(name, p_all(&source));
+      forms.extend(body);
+      self.exports.insert(name.to_string(), exports);
+    }
+    for export in &self.exports[name] {
+      let alias = match prefix {
+        Some(prefix) => format!("{prefix}/{export}"),
+        None => export.clone(),
+      };
+      forms.push(Val::List(vec![Val::Sym("define".to_string()), Val::Sym(alias), Val::Sym(format!("{name}/{export}"))]));
+    }
+    let mut loaded = vec![Val::Sym("module-loaded".to_string()), Val::String(name.to_string())];
+    loaded.extend(prefix.map(|prefix| Val::String(prefix.to_string())));
+    forms.push(Val::List(loaded));
+
+    forms.insert(0, Val::Sym("do".to_string()));
+    self.pending.push((auto, Val::List(forms)));
+    self.bound.insert(key, Require::Queued);
+    None
+  }
+
+  /// Takes the module code to start this frame.
+  pub fn take_pending(&mut self, tick: u64) -> Vec<(AutoNdx, Val)> {
+    for require in self.bound.values_mut() {
+      if *require == Require::Queued {
+        *require = Require::Started(tick);
+      }
+    }
+    std::mem::take(&mut self.pending)
+  }
+
+  /// Handles the `(module-loaded name [prefix])` a module's code ends with. Only a require that's under
+  /// way can be finished, so scripts can't mark modules loaded themselves.
+  pub fn loaded(&mut self, auto: AutoNdx, name: &str, prefix: Option<&str>) -> bool {
+    let key = (auto, name.to_string(), prefix.map(str::to_string));
+    if !matches!(self.bound.get(&key), Some(Require::Started(_))) {
+      return false;
+    }
+    self.loaded.insert((auto, name.to_string()));
+    self.bound.insert(key, Require::Done);
+    true
+  }
+
+  /// Drops an auto's unfinished requires, as when its program starts over.
+  pub fn abandon(&mut self, auto: AutoNdx) {
+    self.bound.retain(|(other, _, _), require| *other != auto || *require == Require::Done);
+    self.pending.retain(|(other, _)| *other != auto);
+  }
+
+  pub fn add_path(&mut self, path: &str) {
+    if !self.paths.iter().any(|existing| existing == path) {
+      self.paths.push(path.to_string());
+    }
+  }
+
+  fn read(&self, name: &str) -> Result<String, String> {
+    if name.split('/').any(|part| part.is_empty() || part == "." || part == "..") {
+      return Err(format!("error: bad module name {name}"));
+    }
+    self.paths.iter()
+      .map(|path| Path::new(path).join(format!("{name}.cnvr")))
+      .find_map(|path| std::fs::read_to_string(path).ok())
+      .ok_or_else(|| format!("error: no module {name} in {}", self.paths.join(", ")))
+  }
+}
+
+/// Renames a module's top-level definitions to `name/definition` everywhere they appear, and takes out
+/// its `(provide ...)` forms. Returns the forms left and the exported names, which are all of them if
+/// nothing is provided.
+pub fn module_body(name: &str, forms: Vec<Val>) -> (Vec<Val>, Vec<String>) {
+  let mut defined = vec![];
+  let mut provided = None;
+  let mut body = vec![];
+  for form in forms {
+    if let Val::List(list) = &form {
+      match (list.get(0), list.get(1)) {
+        (Some(Val::Sym(head)), _) if head == "provide" => {
+          provided.get_or_insert_with(Vec::new).extend(list.iter().skip(1).filter_map(sym_name));
+          continue;
+        }
+        (Some(Val::Sym(head)), Some(Val::Sym(defined_name))) if head == "define" => defined.push(defined_name.clone()),
+        (Some(Val::Sym(head)), Some(Val::List(lambda))) if head == "define" => defined.extend(lambda.get(0).and_then(sym_name)),
+        _ => {}
+      }
+    }
+    body.push(form);
+  }
+
+  let names = defined.iter().cloned().collect::<HashSet<String>>();
+  let body = body.iter().map(|form| rename(form, name, &names)).collect();
+  (body, provided.unwrap_or(defined))
+}
+
+fn rename(val: &Val, module: &str, names: &HashSet<String>) -> Val {
+  match val {
+    Val::Sym(sym) if names.contains(sym) => Val::Sym(format!("{module}/{sym}")),
+    Val::List(list) => Val::List(list.iter().map(|val| rename(val, module, names)).collect()),
+    _ => val.clone(),
+  }
+}
+
+fn sym_name(val: &Val) -> Option<String> {
+  match val {
+    Val::Sym(sym) => Some(sym.clone()),
+    _ => None,
+  }
+}
+
+#[test]
+fn test_module_body() {
+  let (body, exports) = module_body("velocity", p_all("
+    (provide goto)
+    (define move (lambda (route) (step route) (move route)))
+    (define (goto x y) (move (route x y)))
+  "));
+  assert_eq!(exports, vec!["goto".to_string()]);
+  assert_eq!(body, p_all("
+    (define velocity/move (lambda (route) (step route) (velocity/move route)))
+    (define (velocity/goto x y) (velocity/move (route x y)))
+  "));
+
+  let (_, exports) = module_body("util", p_all("(define a 1) (define (b) a)"));
+  assert_eq!(exports, vec!["a".to_string(), "b".to_string()]);
+}
+
+#[test]
+fn test_require_failed() {
+  let dir = std::env::temp_dir().join(format!("rs98-failed-modules-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  std::fs::write(dir.join("flaky.cnvr"), "(define a 1)").unwrap();
+  let mut modules = Modules { paths: vec![dir.to_string_lossy().to_string()], ..Modules::default() };
+  let auto = AutoNdx(1);
+
+  assert_eq!(modules.require(auto, "flaky", None, 1), None);
+  assert_eq!(modules.take_pending(1).len(), 1);
+  assert_eq!(modules.require(auto, "flaky", None, 1), None);
+
+  // back at the require without the module's code having reached its end
+  assert!(matches!(modules.require(auto, "flaky", None, 2), Some(Err(_))));
+
+  // so the next require tries again, and only it can be finished
+  assert_eq!(modules.require(auto, "flaky", None, 3), None);
+  assert!(!modules.loaded(auto, "flaky", None));
+  modules.take_pending(3);
+  assert!(!modules.loaded(auto, "other", None));
+  assert!(modules.loaded(auto, "flaky", None));
+  assert_eq!(modules.require(auto, "flaky", None, 5), Some(Ok(())));
+  std::fs::remove_dir_all(&dir).ok();
+}
@@ -1,20 +1,42 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
 
-use bevy::prelude::*;
-use conniver::{Val, State, eval_s, p};
+use bevy::{prelude::*, app::AppExit};
+use conniver::{Val, State, eval_s, p, val::p_all, object::read_string};
 
-use crate::model::{auto::AutoNdx, world::World};
+use crate::model::{auto::AutoNdx, world::World, event::WorldEvent, act::Action};
 
-use super::message::{MessageHandler, get_message_handlers};
+use super::{behaviour::{Behaviour, behaviour_tree}, capability::Capability, debug::Debugger, message::{Message, MessageHandler, get_message_handlers}, signature::Signature, reload::{ReloadWatcher, watch_reload}, schedule::Scheduler, timer::Timers, module::Modules, error::{ScriptError, ERROR_LOG_SIZE}, remote::poll_remote, registry::{MessageRegistry, apply_message_registry}};
+
+/// How many procs get to run each frame.
+pub const DEFAULT_BUDGET: usize = 256;
+
+/// How many steps each proc gets to run for on its turn.
+pub const DEFAULT_SLICE: usize = 1000;
+
+/// How many lines of output are kept for consoles to pick up.
+pub const OUTPUT_SIZE: usize = 256;
+
+/// How long an on-quit script gets to finish its autosave before the app exits anyway.
+pub const QUIT_GRACE_TICKS: u64 = 100;
+
+/// How many unread messages an auto can have before sends to it fail.
+pub const MAILBOX_SIZE: usize = 64;
 
 pub struct RS98ProgramPlugin;
 
 impl Plugin for RS98ProgramPlugin {
   fn build(&self, app: &mut App) {
     app
+      .init_resource::<MessageRegistry>()
       .insert_resource(ProgramSpace::new_load(AutoNdx(0)))
+      // plugins may register messages before or after this one, so they're all added at startup
+      .add_startup_system(apply_message_registry)
+      .insert_resource(ReloadWatcher::new(&["assets/cnvr/kinds.cnvr", "assets/cnvr/patterns.cnvr"]))
       .add_system(update_program)
       .add_system(process_messages)
+      .add_system(watch_reload)
+      .add_system(poll_remote)
+      .add_system(exit_on_quit.after(process_messages))
       ;
   }
 }
@@ -34,49 +56,86 @@ pub fn process_messages(
   program.process_messages(&mut world);
 }
 
+pub fn exit_on_quit(
+  program: Res<ProgramSpace>,
+  mut exit: EventWriter<AppExit>,
+) {
+  if program.ready_to_quit() {
+    exit.send(AppExit);
+  }
+}
+
 #[derive(Resource)]
 pub struct ProgramSpace {
   procs: Vec<State>,
   proto: State,
   pub access: AutoNdx,
-  message_handlers: HashMap<String, MessageHandler>,
+  pub scheduler: Scheduler,
+  pub tick: u64,
+  pub timers: Timers,
+  pub debugger: Debugger,
+  pub modules: Modules,
+  /// The tick `quit` was called on, if it has been.
+  pub quit_requested: Option<u64>,
+  /// Run on auto 0 before quitting, to save whatever should outlive the session.
+  pub on_quit: Option<Val>,
+  quit_hook_started: bool,
+  pub output: Vec<String>,
+  /// How many lines have ever been printed, for consoles to keep their place in `output` with.
+  pub output_count: u64,
+  errors: Vec<Vec<ScriptError>>,
+  capabilities: Vec<Capability>,
+  mailboxes: Vec<VecDeque<(AutoNdx, Val)>>,
+  mail_handlers: Vec<Option<String>>,
+  subscriptions: Vec<HashSet<String>>,
+  /// Programs to switch to next frame, set by `set-program`.
+  next_programs: Vec<(AutoNdx, Val)>,
+  message_handlers: HashMap<String, Message>,
+  /// The task each auto is carrying out with `work-task`, to notice when it's taken back.
+  pub working: HashMap<AutoNdx, u64>,
+  /// Trees defined with `define-behaviour`, for programs like `(behaviour haul)`.
+  pub named_behaviours: HashMap<String, Val>,
+  /// Each auto's behaviour tree, if its program is one, with the tree it was parsed from.
+  behaviours: Vec<Option<(Val, Result<Behaviour, String>)>>,
 }
 
 impl ProgramSpace {
   pub fn new(access: AutoNdx) -> Self {
-    let mut proto = State::new();
-    proto.load_lib();
     let message_handlers = get_message_handlers();
-    for (name, _) in message_handlers.iter() {
-      proto.message_add(name);
-    }
-    //eval_s(&p("(load \"assets/cnvr/lib.cnvr\")"), &mut proto);
-    eval_s(&p("(load \"assets/cnvr/velocity.cnvr\")"), &mut proto);
+    let proto = new_proto(&message_handlers, &[p("(load \"assets/cnvr/velocity.cnvr\")")]);
     Self {
       procs: Vec::new(),
       proto,
       access,
+      scheduler: Scheduler::new(DEFAULT_BUDGET, DEFAULT_SLICE),
+      tick: 0,
+      timers: Timers::default(),
+      debugger: Debugger::default(),
+      modules: Modules::default(),
+      quit_requested: None,
+      on_quit: None,
+      quit_hook_started: false,
+      output: Vec::new(),
+      output_count: 0,
+      errors: Vec::new(),
+      capabilities: Vec::new(),
+      mailboxes: Vec::new(),
+      mail_handlers: Vec::new(),
+      subscriptions: Vec::new(),
+      next_programs: Vec::new(),
       message_handlers,
+      working: HashMap::new(),
+      named_behaviours: HashMap::new(),
+      behaviours: Vec::new(),
     }
   }
 
+  /// Like `new`, with the given library instead of velocity.cnvr.
   #[cfg(test)]
-  pub fn new_lib_override(access: AutoNdx, lib: &Vec<Val>) -> Self {
-    let mut proto = State::new();
-    proto.load_lib();
-    let message_handlers = get_message_handlers();
-    for (name, _) in message_handlers.iter() {
-      proto.message_add(name);
-    }
-    for val in lib {
-      eval_s(val, &mut proto);
-    }
-    Self {
-      procs: Vec::new(),
-      proto,
-      access,
-      message_handlers,
-    }
+  pub fn new_lib_override(access: AutoNdx, lib: &[Val]) -> Self {
+    let mut result = Self::new(access);
+    result.proto = new_proto(&result.message_handlers, lib);
+    result
   }
 
   pub fn new_load(access: AutoNdx) -> Self {
@@ -92,6 +151,16 @@ impl ProgramSpace {
       return;
     }
     self.procs.resize(size, self.proto.clone());
+    self.errors.resize(size, Vec::new());
+    self.capabilities.resize(size, Capability::Robot);
+    if old_size == 0 {
+      // auto 0 runs the loader
+      self.capabilities[0] = Capability::Admin;
+    }
+    self.mailboxes.resize(size, VecDeque::new());
+    self.mail_handlers.resize(size, None);
+    self.subscriptions.resize(size, HashSet::new());
+    self.behaviours.resize(size, None);
     for i in old_size..size {
       self.procs[i].set_var(&"me".to_string(), Val::Num(i as f32));
     }
@@ -100,44 +169,267 @@ impl ProgramSpace {
   #[cfg(test)]
   pub fn set_program(&mut self, robo: AutoNdx, p: Val) {
     self.ensure_size(robo.0);
+    self.timers.wake(robo);
     self.procs[robo.0].set_program(p);
   }
 
   pub fn update(&mut self, _dur: f64) {
-    for state in &mut self.procs {
-      if state.running() {
-        state.run();
+    let running = self.procs.iter().enumerate()
+      .filter(|(ndx, state)| state.running() && !self.debugger.is_paused(AutoNdx(*ndx)))
+      .map(|(ndx, _)| AutoNdx(ndx))
+      .collect::<Vec<AutoNdx>>();
+    let slice = self.scheduler.slice;
+    let mut errors = vec![];
+    for auto in self.scheduler.pick(&running, self.access, self.procs.len()) {
+      // a proc in a tight loop yields once its slice is used up, and carries on next tick
+      let state = &mut self.procs[auto.0];
+      let mut steps = 0;
+      while steps < slice && state.running() && state.message_peek().is_none() {
+        state.step();
+        steps += 1;
+      }
+      self.scheduler.charge(auto, steps);
+      if let Some(error) = state.error().filter(|_| !state.running()) {
+        errors.push((auto, ScriptError::from_interpreter(error, self.tick)));
       }
     }
+    for (auto, error) in errors {
+      self.log_error(auto, error);
+    }
   }
 
   pub fn process_messages(&mut self, world: &mut World) {
+    // timers count simulation time, however many frames it takes
+    self.tick = world.tick;
     self.ensure_size(world.autos.len() - 1);
+    self.deliver_events(world);
+    for (auto, expr) in self.timers.due(self.tick) {
+      self.procs[auto.0].interrupt(expr);
+    }
+    if matches!(self.quit_requested, Some(tick) if self.tick > tick) && !self.quit_hook_started {
+      self.quit_hook_started = true;
+      if let Some(on_quit) = self.on_quit.clone() {
+        self.procs[0].interrupt(on_quit);
+      }
+    }
+
+    for (auto, program) in std::mem::take(&mut self.next_programs) {
+      if behaviour_tree(&program).is_some() {
+        // a tree set again starts from the top
+        self.behaviours[auto.0] = None;
+        self.procs[auto.0].set_program(p("(stop)"));
+      }
+      self.start_program(auto, program);
+    }
+    for (auto, module) in self.modules.take_pending(self.tick) {
+      self.procs[auto.0].interrupt(module);
+    }
+
+    self.fail_stalled_leaves(world);
 
     let mut messages = vec![];
+    let mut idle = vec![];
     for (ndx, state) in self.procs.iter_mut().enumerate() {
       let ndx = AutoNdx(ndx);
       if let Some(message) = state.message_peek() {
         if let Some(Val::Sym(message_name)) = message.get(0) {
+          if !self.debugger.allow(ndx, message_name) {
+            continue;
+          }
           if let Some(handler) = self.message_handlers.get(message_name) {
-            messages.push((message, *handler, ndx));
+            messages.push((message, handler.handler, ndx));
           }
         }
-      } else if state.finished() {
-        let kind = world.get_auto(ndx).kind;
-        let program = world.kinds.get_data(kind).program.clone();
-        if !program.is_nil() {
-          state.set_program(program);
-        }
+      } else if state.finished() && !self.debugger.is_paused(ndx) {
+        idle.push(ndx);
       }
     }
+    for ndx in idle {
+      self.restart(ndx, world);
+    }
 
     for (message, handler, ndx) in messages {
-      let result = handler(message, self, world, ndx);
+      let result = self.dispatch(handler, message.clone(), world, ndx);
       if let Some(result) = result {
+        if let Some(error) = ScriptError::from_result(&message, &result, self.tick) {
+          self.log_error(ndx, error);
+        }
         self.procs[ndx.0].message_return(result);
+        self.debugger.handled(ndx);
       }
     }
+
+    self.deliver_mail();
+  }
+
+  /// Queues a message for another auto. Returns false if its mailbox is full.
+  pub fn send(&mut self, from: AutoNdx, to: AutoNdx, message: Val) -> bool {
+    self.ensure_size(to.0);
+    let mailbox = &mut self.mailboxes[to.0];
+    if mailbox.len() >= MAILBOX_SIZE {
+      return false;
+    }
+    mailbox.push_back((from, message));
+    true
+  }
+
+  pub fn receive(&mut self, auto: AutoNdx) -> Option<(AutoNdx, Val)> {
+    self.ensure_size(auto.0);
+    self.mailboxes[auto.0].pop_front()
+  }
+
+  pub fn set_mail_handler(&mut self, auto: AutoNdx, handler: Option<String>) {
+    self.ensure_size(auto.0);
+    self.mail_handlers[auto.0] = handler;
+  }
+
+  /// Autos with an on-message handler get one message a frame, as an interrupt calling
+  /// `(handler sender 'message)`.
+  fn deliver_mail(&mut self) {
+    for ndx in 0..self.procs.len() {
+      let handler = if let Some(handler) = &self.mail_handlers[ndx] {
+        handler.clone()
+      } else {
+        continue;
+      };
+      if let Some((from, message)) = self.mailboxes[ndx].pop_front() {
+        self.procs[ndx].interrupt(Val::List(vec![Val::Sym(handler), Val::Num(from.0 as f32), quote(message)]));
+      }
+    }
+  }
+
+  /// Asks the app to shut down once the on-quit script, if any, has had its chance to run.
+  pub fn request_quit(&mut self) {
+    if self.quit_requested.is_some() {
+      return;
+    }
+    // the on-quit script starts next frame, since the caller may be auto 0 waiting on this message
+    self.quit_requested = Some(self.tick);
+  }
+
+  pub fn ready_to_quit(&self) -> bool {
+    match self.quit_requested {
+      Some(tick) if self.on_quit.is_none() => self.tick >= tick,
+      Some(tick) => (self.quit_hook_started && self.idle(AutoNdx(0))) || self.tick >= tick + QUIT_GRACE_TICKS,
+      None => false,
+    }
+  }
+
+  pub fn subscribe(&mut self, auto: AutoNdx, event: &str) {
+    self.ensure_size(auto.0);
+    self.subscriptions[auto.0].insert(event.to_string());
+  }
+
+  pub fn unsubscribe(&mut self, auto: AutoNdx, event: &str) {
+    self.ensure_size(auto.0);
+    self.subscriptions[auto.0].remove(event);
+  }
+
+  /// Interrupts subscribed autos with the world's events. An event goes to the auto it happened to, or,
+  /// for autos coming and going, to every auto whose force can see it.
+  fn deliver_events(&mut self, world: &mut World) {
+    for event in std::mem::take(&mut world.events) {
+      let message = event_message(&event, world);
+      for ndx in 0..self.procs.len().min(world.autos.len()) {
+        if !self.subscriptions[ndx].contains(event.name()) {
+          continue;
+        }
+        let hears = if event.is_public() {
+          world.auto_visible(world.get_auto(AutoNdx(ndx)).force, event.auto())
+        } else {
+          event.auto() == AutoNdx(ndx)
+        };
+        if hears {
+          self.procs[ndx].interrupt(message.clone());
+        }
+      }
+    }
+  }
+
+  pub fn log_error(&mut self, auto: AutoNdx, error: ScriptError) {
+    self.ensure_size(auto.0);
+    self.print(format!("error in auto {}: {}", auto.0, error.describe()));
+    let log = &mut self.errors[auto.0];
+    log.push(error);
+    if log.len() > ERROR_LOG_SIZE {
+      log.remove(0);
+    }
+  }
+
+  pub fn errors(&self, auto: AutoNdx) -> &[ScriptError] {
+    self.errors.get(auto.0).map_or(&[], |log| log.as_slice())
+  }
+
+  pub fn last_error(&self, auto: AutoNdx) -> Option<&ScriptError> {
+    self.errors(auto).last()
+  }
+
+  /// Runs a message through the handler table directly, as if `auto` had sent it. `Ok(None)` means the
+  /// message started something that isn't finished yet, like an action.
+  pub fn call(&mut self, world: &mut World, auto: AutoNdx, message: Vec<Val>) -> Result<Option<Val>, String> {
+    let name = if let Some(Val::Sym(name)) = message.get(0) {
+      name.clone()
+    } else {
+      return Err("bad message".to_string());
+    };
+    let handler = if let Some(handler) = self.message_handlers.get(&name) {
+      handler.handler
+    } else {
+      return Err(format!("unknown message {name}"));
+    };
+    self.ensure_size(auto.0);
+    Ok(self.dispatch(handler, message, world, auto))
+  }
+
+  /// Runs a handler if the auto's capability allows the message and the arguments fit its signature,
+  /// and returns an error otherwise.
+  fn dispatch(&mut self, handler: MessageHandler, message: Vec<Val>, world: &mut World, auto: AutoNdx) -> Option<Val> {
+    let name = read_string(&message[0]);
+    if !self.capability(auto).allows(&name) {
+      return Some(Val::String(format!("error: {name} is not permitted for {}", self.capability(auto).to_str())));
+    }
+    let args = match self.message_handlers.get(&name).map(|handler| handler.signature.parse_args(&message, world)) {
+      Some(Ok(args)) => args,
+      Some(Err(error)) => return Some(Val::String(error)),
+      None => return Some(Val::String(format!("error: unknown message {name}"))),
+    };
+    handler(args, self, world, auto)
+  }
+
+  /// Adds or replaces a message after the program space has been built.
+  pub fn add_message(&mut self, spec: &str, handler: MessageHandler) {
+    let signature = Signature::parse(spec);
+    self.proto.message_add(&signature.name);
+    for state in &mut self.procs {
+      state.message_add(&signature.name);
+    }
+    self.message_handlers.insert(signature.name.clone(), Message { signature, handler });
+  }
+
+  pub fn signature(&self, name: &str) -> Option<&Signature> {
+    self.message_handlers.get(name).map(|handler| &handler.signature)
+  }
+
+  /// Every message scripts can send, by name.
+  pub fn signatures(&self) -> Vec<&Signature> {
+    let mut result = self.message_handlers.values().map(|handler| &handler.signature).collect::<Vec<&Signature>>();
+    result.sort_by(|a, b| a.name.cmp(&b.name));
+    result
+  }
+
+  pub fn capability(&self, auto: AutoNdx) -> Capability {
+    self.capabilities.get(auto.0).copied().unwrap_or_default()
+  }
+
+  pub fn set_capability(&mut self, auto: AutoNdx, capability: Capability) {
+    self.ensure_size(auto.0);
+    self.capabilities[auto.0] = capability;
+  }
+
+  /// Whether an auto's script may change what another auto holds.
+  pub fn may_modify(&self, world: &World, auto: AutoNdx, other: AutoNdx) -> bool {
+    self.capability(auto) == Capability::Admin
+      || (other.0 < world.autos.len() && world.get_auto(other).force == world.get_auto(auto).force)
   }
 
   pub fn interrupt(&mut self, robo: AutoNdx, message: Val) {
@@ -145,7 +437,34 @@ impl ProgramSpace {
     self.procs[robo.0].interrupt(message);
   }
 
-  #[cfg(test)]
+  /// Evaluates typed-in source on an auto, printing the result of its last form when it's done, or
+  /// printing why it couldn't be read.
+  pub fn eval(&mut self, robo: AutoNdx, source: &str) {
+    match parse_body(source) {
+      Ok(body) => self.interrupt(robo, Val::List(vec![Val::Sym("console-return".to_string()), body])),
+      Err(error) => self.print(format!("{}> error: {error}", robo.0)),
+    }
+  }
+
+  /// Prints a line to stdout and keeps it for whichever console is watching.
+  pub fn print(&mut self, line: String) {
+    println!("{line}");
+    self.output.push(line);
+    self.output_count += 1;
+    if self.output.len() > OUTPUT_SIZE {
+      self.output.remove(0);
+    }
+  }
+
+  /// The lines printed since a console's cursor that are still kept, moving the cursor past them. Each
+  /// console has its own cursor, so every one of them sees every line.
+  pub fn output_since(&self, cursor: &mut u64) -> Vec<String> {
+    let first = self.output_count - self.output.len() as u64;
+    let start = (*cursor).max(first);
+    *cursor = self.output_count;
+    self.output[(start - first) as usize..].to_vec()
+  }
+
   pub fn idle(&self, robo: AutoNdx) -> bool {
     if self.procs.len() <= robo.0 {
       return true;
@@ -153,11 +472,121 @@ impl ProgramSpace {
     self.procs[robo.0].finished()
   }
 
+  /// Replaces an auto's running program next frame, since the caller may be that auto, waiting on a
+  /// message.
+  pub fn replace_program(&mut self, auto: AutoNdx, program: Val) {
+    self.ensure_size(auto.0);
+    self.next_programs.retain(|(other, _)| *other != auto);
+    self.next_programs.push((auto, program));
+  }
+
   pub fn init_auto(&mut self, auto: AutoNdx, world: &mut World) {
     self.ensure_size(auto.0);
-    let kind = world.get_auto(auto).kind;
-    let program = world.kinds.get_data(kind).program.clone();
-    self.procs[auto.0].set_program(program);
+    self.restart(auto, world);
+  }
+
+  /// Starts an idle auto's program over.
+  fn restart(&mut self, auto: AutoNdx, world: &World) {
+    self.start_program(auto, world.auto_program(auto));
+  }
+
+  /// Runs a program on an auto, or, if it's `(behaviour tree)`, the tree's next leaf.
+  fn start_program(&mut self, auto: AutoNdx, program: Val) {
+    // whatever the auto was sleeping in or requiring is over
+    self.timers.wake(auto);
+    self.modules.abandon(auto);
+    let tree = match behaviour_tree(&program) {
+      Some(tree) => tree,
+      None => {
+        if !program.is_nil() {
+          self.procs[auto.0].set_program(program);
+        }
+        return;
+      }
+    };
+
+    // a named tree is looked up every time, so defining or redefining it reaches autos already running it
+    let named = match &tree {
+      Val::Sym(name) => self.named_behaviours.get(name).cloned(),
+      _ => None,
+    };
+    let tree = named.unwrap_or(tree);
+    if !matches!(&self.behaviours[auto.0], Some((source, _)) if *source == tree) {
+      let behaviour = Behaviour::parse(&tree);
+      if let Err(error) = &behaviour {
+        let error = ScriptError { message: "behaviour".to_string(), args: vec![tree.clone()], error: error.clone(), tick: self.tick };
+        self.log_error(auto, error);
+      }
+      self.behaviours[auto.0] = Some((tree, behaviour));
+    }
+    if let Some((_, Ok(behaviour))) = &mut self.behaviours[auto.0] {
+      if behaviour.leaf_running() {
+        // the leaf's program was replaced before it could report back
+        behaviour.finish_leaf(false);
+      }
+      if let Some(leaf) = behaviour.tick() {
+        self.procs[auto.0].set_program(leaf);
+      }
+    }
+  }
+
+  /// A stalled action fails its behaviour tree leaf rather than waiting forever.
+  fn fail_stalled_leaves(&mut self, world: &mut World) {
+    for ndx in 0..self.procs.len().min(world.autos.len()) {
+      if let Some((_, Ok(behaviour))) = &mut self.behaviours[ndx] {
+        if behaviour.leaf_running() && world.stall_message(AutoNdx(ndx)).is_some() {
+          behaviour.finish_leaf(false);
+          world.set_auto_action(AutoNdx(ndx), Action::Stop);
+          world.get_auto_mut(AutoNdx(ndx)).stall_message = None;
+          self.timers.wake(AutoNdx(ndx));
+          self.procs[ndx].set_program(p("(stop)"));
+        }
+      }
+    }
+  }
+
+  /// Handles `behaviour-result`, which each leaf's program ends with.
+  pub fn finish_behaviour_leaf(&mut self, auto: AutoNdx, success: bool) {
+    self.ensure_size(auto.0);
+    if let Some((_, Ok(behaviour))) = &mut self.behaviours[auto.0] {
+      behaviour.finish_leaf(success);
+    }
+  }
+
+  /// Where an auto is in its behaviour tree, for the UI and `behaviour-status`.
+  pub fn behaviour_status(&self, auto: AutoNdx) -> Option<String> {
+    match self.behaviours.get(auto.0) {
+      Some(Some((_, Ok(behaviour)))) => Some(behaviour.describe()),
+      Some(Some((_, Err(error)))) => Some(error.clone()),
+      _ => None,
+    }
+  }
+
+  /// Runs a debugger command against an auto: pause, resume, step, where, break NAME, unbreak NAME,
+  /// breaks, or var NAME.
+  pub fn debug_command(&mut self, auto: AutoNdx, command: &str, arg: Option<&str>) -> Result<String, String> {
+    if auto.0 >= self.procs.len() {
+      return Err(format!("no auto {}", auto.0));
+    }
+    match (command, arg) {
+      ("pause", _) => self.debugger.pause(auto),
+      ("resume", _) => self.debugger.resume(auto),
+      ("step", _) => self.debugger.step(auto),
+      ("where", _) => {
+        let state = if self.debugger.is_paused(auto) { "paused" } else { "running" };
+        return Ok(match self.procs[auto.0].message_peek() {
+          Some(message) => format!("{state} at {}", read_string(&Val::List(message))),
+          None if self.procs[auto.0].finished() => format!("{state}, idle"),
+          None => state.to_string(),
+        });
+      }
+      ("break", Some(message)) => self.debugger.set_breakpoint(auto, message),
+      ("unbreak", Some(message)) => self.debugger.clear_breakpoint(auto, message),
+      ("breaks", _) => return Ok(self.debugger.breakpoints(auto).join(" ")),
+      ("var", Some(name)) => return Ok(read_string(&self.get_var(auto, &name.to_string()))),
+      _ => return Err("usage: debug auto pause|resume|step|where|break name|unbreak name|breaks|var name".to_string()),
+    }
+    Ok(String::new())
   }
 
   pub fn get_var(&self, robo: AutoNdx, name: &String) -> Val {
@@ -167,3 +596,84 @@ impl ProgramSpace {
     self.procs[robo.0].get_var(name).cloned().unwrap_or_default()
   }
 }
+
+/// Reads typed-in source into its forms, rejecting anything unbalanced rather than guessing.
+pub(crate) fn parse_source(source: &str) -> Result<Vec<Val>, String> {
+  let mut depth = 0;
+  let mut in_string = false;
+  let mut escaped = false;
+  for c in source.chars() {
+    match c {
+      _ if escaped => escaped = false,
+      '\\' if in_string => escaped = true,
+      '"' => in_string = !in_string,
+      '(' if !in_string => depth += 1,
+      ')' if !in_string => {
+        depth -= 1;
+        if depth < 0 {
+          return Err("unexpected )".to_string());
+        }
+      }
+      _ => {}
+    }
+  }
+  if in_string {
+    return Err("unterminated string".to_string());
+  }
+  if depth > 0 {
+    return Err("missing )".to_string());
+  }
+  let forms = p_all(source);
+  if forms.is_empty() {
+    return Err("nothing to evaluate".to_string());
+  }
+  Ok(forms)
+}
+
+/// Reads typed-in source as one expression, running several forms in turn with `do`.
+pub(crate) fn parse_body(source: &str) -> Result<Val, String> {
+  let mut forms = parse_source(source)?;
+  Ok(if forms.len() == 1 {
+    forms.remove(0)
+  } else {
+    Val::List([vec![Val::Sym("do".to_string())], forms].concat())
+  })
+}
+
+/// A proc with the lisp library, every message, and `lib` evaluated, for each auto's proc to start from.
+fn new_proto(message_handlers: &HashMap<String, Message>, lib: &[Val]) -> State {
+  let mut proto = State::new();
+  proto.load_lib();
+  for name in message_handlers.keys() {
+    proto.message_add(name);
+  }
+  for val in lib {
+    eval_s(val, &mut proto);
+  }
+  proto
+}
+
+fn quote(val: Val) -> Val {
+  if let Val::List(mut quoted) = p("'x") {
+    quoted[1] = val;
+    Val::List(quoted)
+  } else {
+    val
+  }
+}
+
+/// The call an event turns into, like `(item-arrived x y 'kind)`.
+fn event_message(event: &WorldEvent, world: &World) -> Val {
+  let mut message = vec![Val::Sym(event.name().to_string())];
+  match event {
+    WorldEvent::ActionStalled(_, stall) => message.push(Val::String(stall.clone())),
+    WorldEvent::ItemArrived(_, loc, kind) => {
+      message.push(Val::Num(loc.x as f32));
+      message.push(Val::Num(loc.y as f32));
+      message.push(quote(Val::Sym(world.kinds.name(*kind))));
+    }
+    WorldEvent::AutoCreated(auto) | WorldEvent::AutoDestroyed(auto) => message.push(Val::Num(auto.0 as f32)),
+    WorldEvent::ActionFinished(_) | WorldEvent::Produced(_) => {}
+  }
+  Val::List(message)
+}
@@ -0,0 +1,108 @@
+use bevy::prelude::*;
+use conniver::Val;
+
+use crate::model::{act::{Action, CustomAction}, auto::AutoNdx, world::World};
+
+use super::{message::{MessageHandler, action_handler}, program::ProgramSpace, signature::Args};
+
+/// Messages and actions added by other crates' plugins, on top of the built-in ones.
+#[derive(Resource, Default, Clone)]
+pub struct MessageRegistry {
+  /// Signature specs and their handlers.
+  pub messages: Vec<(String, MessageHandler)>,
+  pub actions: Vec<(String, CustomAction)>,
+}
+
+impl MessageRegistry {
+  pub fn add_message(&mut self, spec: &str, handler: MessageHandler) {
+    self.messages.push((spec.to_string(), handler));
+  }
+
+  /// Adds an action, along with a message of the same name that performs it.
+  pub fn add_action(&mut self, name: &str, behaviour: CustomAction) {
+    self.actions.push((name.to_string(), behaviour));
+    self.add_message(name, custom_action_handler);
+  }
+
+  /// Registers everything with a program space and world that already exist.
+  pub fn apply(&self, program: &mut ProgramSpace, world: &mut World) {
+    for (name, behaviour) in &self.actions {
+      world.add_custom_action(name, *behaviour);
+    }
+    for (spec, handler) in &self.messages {
+      program.add_message(spec, *handler);
+    }
+  }
+}
+
+fn custom_action_handler(args: Args, _: &mut ProgramSpace, world: &mut World, auto: AutoNdx) -> Option<Val> {
+  if let Some(ndx) = world.custom_action(&args.name) {
+    action_handler(world, auto, Action::Custom(ndx))
+  } else {
+    Some(Val::String(format!("error: unknown action {}", args.name)))
+  }
+}
+
+/// Lets plugins add script messages and actions, whether they are added before or after
+/// `RS98ProgramPlugin`.
+pub trait ScriptAppExt {
+  fn add_script_message(&mut self, spec: &str, handler: MessageHandler) -> &mut Self;
+  fn add_script_action(&mut self, name: &str, behaviour: CustomAction) -> &mut Self;
+}
+
+impl ScriptAppExt for App {
+  fn add_script_message(&mut self, spec: &str, handler: MessageHandler) -> &mut Self {
+    self.init_resource::<MessageRegistry>();
+    self.world.resource_mut::<MessageRegistry>().add_message(spec, handler);
+    self
+  }
+
+  fn add_script_action(&mut self, name: &str, behaviour: CustomAction) -> &mut Self {
+    self.init_resource::<MessageRegistry>();
+    self.world.resource_mut::<MessageRegistry>().add_action(name, behaviour);
+    self
+  }
+}
+
+/// Adds everything plugins registered, once they've all been built.
+pub fn apply_message_registry(
+  registry: Res<MessageRegistry>,
+  mut program: ResMut<ProgramSpace>,
+  mut world: ResMut<World>,
+) {
+  registry.apply(&mut program, &mut world);
+}
+
+#[test]
+fn test_registry() {
+  use bevy::prelude::IVec2;
+  use conniver::p;
+
+  fn spin(world: &mut World, auto: AutoNdx) -> Option<String> {
+    world.get_auto_mut(auto).loc += IVec2::new(1, 0);
+    world.finish_auto_action(auto);
+    None
+  }
+
+  let mut registry = MessageRegistry::default();
+  registry.add_message("double n:int", |args, _, _, _| Some(Val::Num((args.int(0) * 2) as f32)));
+  registry.add_action("spin", spin);
+
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  let mut program = ProgramSpace::new(space);
+  registry.apply(&mut program, &mut world);
+  assert_eq!(program.signature("double").unwrap().usage(), "(double n)");
+
+  program.interrupt(space, p("(do
+    (define d (double 21))
+    (spin)
+  )"));
+  for _ in 0..10 {
+    program.update(1.0);
+    program.process_messages(&mut world);
+    world.update(1.0);
+  }
+  assert_eq!(program.get_var(space, &"d".to_string()), Val::Num(42.0));
+  assert_eq!(world.get_auto(space).loc, IVec2::new(1, 0));
+}
@@ -0,0 +1,78 @@
+use std::{collections::HashMap, time::SystemTime};
+
+use bevy::prelude::*;
+use conniver::p;
+
+use crate::model::auto::AutoNdx;
+
+use super::program::ProgramSpace;
+
+/// Polls definition files and reloads them into the world when they change on disk.
+#[derive(Resource)]
+pub struct ReloadWatcher {
+  files: Vec<String>,
+  modified: HashMap<String, SystemTime>,
+  timer: f64,
+}
+
+impl ReloadWatcher {
+  pub fn new(files: &[&str]) -> Self {
+    let mut result = Self {
+      files: files.iter().map(|file| file.to_string()).collect(),
+      modified: HashMap::new(),
+      timer: 0.0,
+    };
+    // the first poll only records the current times, since load.cnvr already loaded them
+    result.changed();
+    result
+  }
+
+  pub fn changed(&mut self) -> Vec<String> {
+    let mut changed = vec![];
+    for file in &self.files {
+      let modified = std::fs::metadata(file).and_then(|meta| meta.modified());
+      if let Ok(modified) = modified {
+        let old = self.modified.insert(file.clone(), modified);
+        if old.map_or(false, |old| old != modified) {
+          changed.push(file.clone());
+        }
+      }
+    }
+    changed
+  }
+}
+
+pub fn watch_reload(
+  mut watcher: ResMut<ReloadWatcher>,
+  mut program: ResMut<ProgramSpace>,
+  time: Res<Time>,
+) {
+  watcher.timer += time.delta_seconds_f64();
+  if watcher.timer < 1.0 {
+    return;
+  }
+  watcher.timer = 0.0;
+
+  for file in watcher.changed() {
+    println!("reloading {file}");
+    program.interrupt(AutoNdx(0), p(&format!("(do (begin-reload) (load \"{file}\") (end-reload))")));
+  }
+}
+
+#[test]
+fn test_reload_watcher() {
+  let file = std::env::temp_dir().join("rs98-test-reload.cnvr");
+  let file = file.to_str().unwrap().to_string();
+  std::fs::write(&file, "(define-kind rock)").unwrap();
+
+  let mut watcher = ReloadWatcher::new(&[&file, "does/not/exist.cnvr"]);
+  assert!(watcher.changed().is_empty());
+
+  // push the time forward rather than sleeping, since filesystem timestamps can be coarse
+  let later = SystemTime::now() + std::time::Duration::from_secs(10);
+  std::fs::File::options().write(true).open(&file).unwrap().set_modified(later).unwrap();
+  assert_eq!(watcher.changed(), vec![file.clone()]);
+  assert!(watcher.changed().is_empty());
+
+  std::fs::remove_file(&file).unwrap();
+}
@@ -0,0 +1,354 @@
+use std::{collections::HashSet, io::{ErrorKind, Read, Write}, net::{TcpListener, TcpStream, ToSocketAddrs}};
+
+use bevy::prelude::*;
+use conniver::{Val, object::read_string};
+
+use crate::model::{auto::{AutoNdx, auto_action_finished}, world::World};
+
+use super::program::{ProgramSpace, parse_body, parse_source};
+
+#[cfg(test)]
+use crate::model::auto::Auto;
+#[cfg(test)]
+use conniver::p;
+
+/// A line-based control protocol over a local TCP socket. Each request is one line, and each gets one
+/// `ok ...` or `err ...` line back; subscribed events arrive as `event ...` lines between replies.
+///
+///   send auto expr         interrupt an auto with an expression
+///   eval auto expr         interrupt an auto, printing the result to the output
+///   call auto message ...  run any script message on behalf of an auto, right now
+///   autos                  list autos as `ndx kind x y parent`, separated by `;`
+///   item auto x y          kind and count in a slot
+///   kinds                  list kind names
+///   patterns               list patterns as `kind (in) -> (out)`, separated by `;`
+///   help                   list script messages as usage strings, separated by `;`
+///   output                 take the lines printed since last time, separated by `;`
+///   step ticks             run the simulation forward
+///   subscribe event        get `finished` or `stalled` events
+///   unsubscribe event
+///   debug auto command     pause, resume, step, where, break name, unbreak name, breaks, or var name
+#[derive(Resource)]
+pub struct RemoteServer {
+  listener: TcpListener,
+  clients: Vec<RemoteClient>,
+  last: Vec<(bool, Option<String>)>,
+}
+
+/// How much unsent output a client may fall behind by before it's dropped.
+const MAX_PENDING: usize = 1 << 20;
+
+struct RemoteClient {
+  stream: TcpStream,
+  buffer: String,
+  /// Replies and events the socket hasn't taken yet.
+  pending: Vec<u8>,
+  subscriptions: HashSet<String>,
+  /// How far through the program's output this client has read.
+  output_cursor: u64,
+  closed: bool,
+}
+
+impl RemoteClient {
+  fn send(&mut self, line: &str) {
+    self.pending.extend_from_slice(format!("{line}\n").as_bytes());
+    if self.pending.len() > MAX_PENDING {
+      self.closed = true;
+    }
+    self.flush();
+  }
+
+  /// Writes as much as the socket will take, keeping the rest for the next poll.
+  fn flush(&mut self) {
+    while !self.pending.is_empty() && !self.closed {
+      match self.stream.write(&self.pending) {
+        Ok(0) => self.closed = true,
+        Ok(len) => {
+          self.pending.drain(..len);
+        }
+        Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+        Err(_) => self.closed = true,
+      }
+    }
+  }
+}
+
+impl RemoteServer {
+  /// Listens for clients. Anyone who connects can drive every auto, so only loopback addresses are
+  /// allowed unless `any_interface` says otherwise.
+  pub fn bind(addr: impl ToSocketAddrs, any_interface: bool) -> std::io::Result<Self> {
+    let addrs = addr.to_socket_addrs()?.collect::<Vec<_>>();
+    if !any_interface && addrs.iter().any(|addr| !addr.ip().is_loopback()) {
+      return Err(std::io::Error::new(ErrorKind::PermissionDenied, "only loopback addresses unless --listen-any is given"));
+    }
+    let listener = TcpListener::bind(&addrs[..])?;
+    listener.set_nonblocking(true)?;
+    Ok(Self {
+      listener,
+      clients: vec![],
+      last: vec![],
+    })
+  }
+
+  #[cfg(test)]
+  pub fn local_addr(&self) -> std::io::Result<std::net::SocketAddr> {
+    self.listener.local_addr()
+  }
+
+  pub fn poll(&mut self, program: &mut ProgramSpace, world: &mut World) {
+    while let Ok((stream, _)) = self.listener.accept() {
+      if stream.set_nonblocking(true).is_ok() {
+        self.clients.push(RemoteClient {
+          stream,
+          buffer: String::new(),
+          pending: vec![],
+          subscriptions: HashSet::new(),
+          output_cursor: program.output_count,
+          closed: false,
+        });
+      }
+    }
+
+    for ndx in 0..self.clients.len() {
+      let client = &mut self.clients[ndx];
+      client.flush();
+      let mut bytes = [0; 1024];
+      loop {
+        match client.stream.read(&mut bytes) {
+          Ok(0) => {
+            client.closed = true;
+            break;
+          }
+          Ok(len) => client.buffer.push_str(&String::from_utf8_lossy(&bytes[..len])),
+          Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+          Err(_) => {
+            client.closed = true;
+            break;
+          }
+        }
+      }
+
+      while let Some(end) = self.clients[ndx].buffer.find('\n') {
+        let line = self.clients[ndx].buffer.drain(..=end).collect::<String>();
+        let line = line.trim();
+        if !line.is_empty() {
+          let reply = match self.handle_request(ndx, line, program, world) {
+            Ok(reply) => format!("ok {reply}"),
+            Err(err) => format!("err {err}"),
+          };
+          self.clients[ndx].send(reply.trim_end());
+        }
+      }
+    }
+
+    self.send_events(world);
+    self.clients.retain(|client| !client.closed);
+  }
+
+  fn handle_request(&mut self, client: usize, line: &str, program: &mut ProgramSpace, world: &mut World) -> Result<String, String> {
+    let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+    let mut words = rest.split_whitespace();
+    match command {
+      "send" | "eval" | "call" => {
+        let auto = parse_auto(words.next(), world)?;
+        let source = rest.trim_start().split_once(' ').map(|(_, source)| source).unwrap_or_default();
+        match command {
+          "send" => {
+            program.interrupt(auto, parse_body(source)?);
+            Ok(String::new())
+          }
+          "eval" => {
+            program.eval(auto, source);
+            Ok(String::new())
+          }
+          _ => {
+            let message = match parse_source(&format!("({source})"))?.as_mut_slice() {
+              [Val::List(message)] => std::mem::take(message),
+              _ => return Err("bad message".to_string()),
+            };
+            match program.call(world, auto, message)? {
+              Some(result) => Ok(read_string(&result)),
+              None => Ok("pending".to_string()),
+            }
+          }
+        }
+      }
+
+      "autos" => Ok(world.autos.iter().enumerate().map(|(ndx, auto)| {
+        format!("{ndx} {} {} {} {}", world.kinds.name(auto.kind), auto.loc.x, auto.loc.y, auto.parent.0)
+      }).collect::<Vec<String>>().join("; ")),
+
+      "item" => {
+        let auto = parse_auto(words.next(), world)?;
+        let loc = IVec2::new(parse_num(words.next())?, parse_num(words.next())?);
+        let item = world.get_item(auto, loc);
+        Ok(format!("{} {}", world.kinds.name(item), world.get_count(auto, loc)))
+      }
+
+      "kinds" => Ok(world.kinds.kinds.iter().map(|kind| kind.name.clone()).collect::<Vec<String>>().join(" ")),
+
+      "patterns" => Ok(world.patterns.patterns.iter().map(|pattern| {
+        let names = |kinds: &[crate::model::kind::Kind]| kinds.iter().map(|kind| world.kinds.name(*kind)).collect::<Vec<String>>().join(" ");
+        format!("{} ({}) -> ({})", world.kinds.name(pattern.for_kind), names(&pattern.input), names(&pattern.output))
+      }).collect::<Vec<String>>().join("; ")),
+
+      "help" => Ok(program.signatures().iter().map(|signature| signature.usage()).collect::<Vec<String>>().join("; ")),
+
+      "output" => Ok(program.output_since(&mut self.clients[client].output_cursor).join("; ")),
+
+      "step" => {
+        let ticks = parse_num(words.next()).unwrap_or(1);
+        // events go out every tick, so none are missed while stepping
+        for _ in 0..ticks {
+          program.update(1.0);
+          program.process_messages(world);
+          world.update(1.0);
+          self.send_events(world);
+        }
+        Ok(world.tick.to_string())
+      }
+
+      "debug" => {
+        let auto = parse_auto(words.next(), world)?;
+        let command = words.next().unwrap_or_default();
+        program.debug_command(auto, command, words.next())
+      }
+
+      "subscribe" | "unsubscribe" => {
+        let event = words.next().ok_or("expected an event")?;
+        if event != "finished" && event != "stalled" {
+          return Err(format!("unknown event {event}"));
+        }
+        let subscriptions = &mut self.clients[client].subscriptions;
+        if command == "subscribe" {
+          subscriptions.insert(event.to_string());
+        } else {
+          subscriptions.remove(event);
+        }
+        Ok(String::new())
+      }
+
+      _ => Err(format!("unknown command {command}")),
+    }
+  }
+
+  fn send_events(&mut self, world: &World) {
+    let mut events = vec![];
+    for (ndx, auto) in world.autos.iter().enumerate() {
+      let now = (auto.flags.get(auto_action_finished), auto.stall_message.clone());
+      if let Some(last) = self.last.get(ndx) {
+        if now.0 && !last.0 {
+          events.push(("finished", format!("event finished {ndx}")));
+        }
+        if let Some(stall) = &now.1 {
+          if last.1.as_ref() != Some(stall) {
+            events.push(("stalled", format!("event stalled {ndx} {stall}")));
+          }
+        }
+      }
+      if self.last.len() <= ndx {
+        self.last.push(now);
+      } else {
+        self.last[ndx] = now;
+      }
+    }
+
+    for client in &mut self.clients {
+      for (event, line) in &events {
+        if client.subscriptions.contains(*event) {
+          client.send(line);
+        }
+      }
+    }
+  }
+}
+
+fn parse_auto(word: Option<&str>, world: &World) -> Result<AutoNdx, String> {
+  let ndx = word.and_then(|word| word.parse::<usize>().ok()).ok_or("expected an auto")?;
+  if ndx < world.autos.len() {
+    Ok(AutoNdx(ndx))
+  } else {
+    Err(format!("no auto {ndx}"))
+  }
+}
+
+fn parse_num(word: Option<&str>) -> Result<i32, String> {
+  word.and_then(|word| word.parse::<i32>().ok()).ok_or_else(|| "expected a number".to_string())
+}
+
+pub fn poll_remote(
+  server: Option<ResMut<RemoteServer>>,
+  mut program: ResMut<ProgramSpace>,
+  mut world: ResMut<World>,
+) {
+  if let Some(mut server) = server {
+    server.poll(&mut program, &mut world);
+  }
+}
+
+#[test]
+fn test_remote() {
+  use std::{io::{BufRead, BufReader}, time::Duration};
+
+  let mut world = World::new_test();
+  world.set_all_tiles(AutoNdx(0), world.kinds.get("grass"));
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(10, 10),
+    parent: AutoNdx(0),
+    ..Auto::default()
+  });
+  world.set_item(AutoNdx(0), IVec2::new(3, 4), world.kinds.get("rock"));
+  let mut program = ProgramSpace::new(AutoNdx(0));
+  let mut server = RemoteServer::bind("127.0.0.1:0", false).unwrap();
+
+  let mut client = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+  client.set_read_timeout(Some(Duration::from_millis(10))).unwrap();
+  let mut reader = BufReader::new(client.try_clone().unwrap());
+  let mut events = vec![];
+
+  // sends a request, and returns the reply along with any events that came before it
+  let mut request = |line: &str, server: &mut RemoteServer, program: &mut ProgramSpace, world: &mut World| {
+    client.write_all(format!("{line}\n").as_bytes()).unwrap();
+    let mut reply = String::new();
+    for _ in 0..500 {
+      server.poll(program, world);
+      if reader.read_line(&mut reply).is_ok() && reply.ends_with('\n') {
+        let line = reply.trim_end().to_string();
+        reply.clear();
+        if line.starts_with("event ") {
+          events.push(line);
+        } else {
+          return line;
+        }
+      }
+    }
+    panic!("no reply to {line}");
+  };
+
+  assert_eq!(request("item 0 3 4", &mut server, &mut program, &mut world), "ok rock 1");
+  assert_eq!(request("call 1 item-at 0 3 4", &mut server, &mut program, &mut world), format!("ok {}", read_string(&p("(\"rock\" 1)"))));
+  assert_eq!(request("autos", &mut server, &mut program, &mut world), "ok 0 space 0 0 0; 1 robo 10 10 0");
+  assert_eq!(request("frobnicate", &mut server, &mut program, &mut world), "err unknown command frobnicate");
+  assert_eq!(request("call 1 frobnicate", &mut server, &mut program, &mut world), "err unknown message frobnicate");
+  assert_eq!(request("call 1 item-at 0 3 4)", &mut server, &mut program, &mut world), "err unexpected )");
+  assert_eq!(request("call 1 item-at) (0 3 4", &mut server, &mut program, &mut world), "err bad message");
+  assert_eq!(request("send 1 (step n", &mut server, &mut program, &mut world), "err missing )");
+  assert_eq!(request("send 1 \"oops", &mut server, &mut program, &mut world), "err unterminated string");
+  assert!(RemoteServer::bind("0.0.0.0:0", false).is_err());
+
+  assert_eq!(request("subscribe finished", &mut server, &mut program, &mut world), "ok");
+  assert_eq!(request("send 1 (step n)", &mut server, &mut program, &mut world), "ok");
+  assert_eq!(request("step 3", &mut server, &mut program, &mut world), "ok 3");
+  assert!(events.contains(&format!("event finished {}", robo.0)));
+  assert_eq!(world.get_auto(robo).loc, IVec2::new(10, 11));
+
+  // output is there for this client even after another console has read it
+  assert_eq!(request("eval 0 (print \"hi\")", &mut server, &mut program, &mut world), "ok");
+  assert_eq!(request("step 3", &mut server, &mut program, &mut world), "ok 6");
+  let mut console_cursor = 0;
+  assert!(program.output_since(&mut console_cursor).contains(&"hi".to_string()));
+  let output = request("output", &mut server, &mut program, &mut world);
+  assert!(output.starts_with("ok ") && output.contains("hi"));
+  assert_eq!(request("output", &mut server, &mut program, &mut world), "ok");
+}
@@ -0,0 +1,145 @@
+use std::io::{BufRead, Write};
+
+use crate::model::{auto::AutoNdx, world::World};
+
+use super::{program::ProgramSpace, registry::MessageRegistry};
+
+#[cfg(test)]
+use bevy::prelude::IVec2;
+#[cfg(test)]
+use crate::model::auto::Auto;
+
+/// How many ticks to wait for an expression to finish before handing the prompt back.
+const MAX_EVAL_TICKS: usize = 100;
+
+/// A terminal front end that runs the world without rendering.
+pub struct Repl {
+  pub world: World,
+  pub program: ProgramSpace,
+  pub target: AutoNdx,
+}
+
+impl Repl {
+  /// Adds the registry's messages and actions first, as the app does at startup, so scripts see the same
+  /// messages with or without rendering.
+  pub fn new(mut world: World, mut program: ProgramSpace, registry: &MessageRegistry) -> Self {
+    registry.apply(&mut program, &mut world);
+    let target = program.access;
+    Self { world, program, target }
+  }
+
+  pub fn step(&mut self, ticks: usize) {
+    for _ in 0..ticks {
+      self.program.update(1.0);
+      self.program.process_messages(&mut self.world);
+      self.world.update(1.0);
+    }
+  }
+
+  pub fn run_until_idle(&mut self, auto: AutoNdx) {
+    let mut ticks = 0;
+    while ticks < MAX_EVAL_TICKS && !self.program.idle(auto) {
+      self.step(1);
+      ticks += 1;
+    }
+  }
+
+  /// Handles one line of input, returning what should be printed. Script output is already echoed by
+  /// `ProgramSpace::print`, and is left in `program.output`.
+  pub fn handle_line(&mut self, line: &str) -> Vec<String> {
+    let line = line.trim();
+    let mut result = vec![];
+    if let Some(command) = line.strip_prefix(':') {
+      let mut words = command.split_whitespace();
+      let name = words.next().unwrap_or_default();
+      let arg = words.next().and_then(|arg| arg.parse::<usize>().ok());
+      match (name, arg) {
+        ("step", ticks) => self.step(ticks.unwrap_or(1)),
+        ("access", Some(auto)) if auto < self.world.autos.len() => {
+          self.program.access = AutoNdx(auto);
+          self.target = AutoNdx(auto);
+        }
+        ("target", Some(auto)) if auto < self.world.autos.len() => self.target = AutoNdx(auto),
+        ("tick", _) => result.push(format!("tick {}", self.world.tick)),
+        _ => result.push("commands: :step [ticks], :access auto, :target auto, :tick, :quit".to_string()),
+      }
+    } else if !line.is_empty() {
+      self.program.eval(self.target, line);
+      self.run_until_idle(self.target);
+    }
+    if self.program.quit_requested.is_some() {
+      // give the on-quit script a chance to finish
+      while !self.program.ready_to_quit() {
+        self.step(1);
+      }
+    }
+
+    if let Some(stall) = &self.world.get_auto(self.target).stall_message {
+      result.push(format!("{} stalled: {stall}", self.target.0));
+    }
+    result
+  }
+}
+
+pub fn run_repl(registry: &MessageRegistry) {
+  let mut repl = Repl::new(World::new_blank(), ProgramSpace::new_load(AutoNdx(0)), registry);
+  repl.run_until_idle(AutoNdx(0));
+  repl.target = repl.program.access;
+
+  let stdin = std::io::stdin();
+  let mut stdout = std::io::stdout();
+  for line in repl.handle_line(":tick") {
+    println!("{line}");
+  }
+  loop {
+    print!("{}> ", repl.target.0);
+    stdout.flush().ok();
+    let mut line = String::new();
+    if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 || line.trim() == ":quit" {
+      break;
+    }
+    for line in repl.handle_line(&line) {
+      println!("{line}");
+    }
+    repl.program.output.clear();
+    if repl.program.ready_to_quit() {
+      break;
+    }
+  }
+}
+
+#[test]
+fn test_repl() {
+  let mut world = World::new_test();
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(10, 10),
+    parent: AutoNdx(0),
+    ..Auto::default()
+  });
+  world.set_all_tiles(AutoNdx(0), world.kinds.get("grass"));
+  world.set_tile(AutoNdx(0), IVec2::new(10, 11), world.kinds.get("wall"));
+  let mut registry = MessageRegistry::default();
+  registry.add_message("double n:int", |args, _, _, _| Some(conniver::Val::Num((args.int(0) * 2) as f32)));
+  let mut repl = Repl::new(world, ProgramSpace::new(AutoNdx(0)), &registry);
+
+  assert_eq!(repl.handle_line(":access 1"), Vec::<String>::new());
+  assert_eq!(repl.program.access, robo);
+  assert_eq!(repl.target, robo);
+
+  repl.handle_line("(car (item-at me 0 0))");
+  assert_eq!(repl.program.output, vec!["1> nothing".to_string()]);
+
+  // messages added by plugins work here just as in the app
+  repl.program.output.clear();
+  repl.handle_line("(double 21)");
+  assert_eq!(repl.program.output, vec![format!("1> {}", conniver::object::read_string(&conniver::Val::Num(42.0)))]);
+
+  // walking into a wall never finishes, so the prompt comes back with the stall
+  let lines = repl.handle_line("(step n)");
+  assert_eq!(lines, vec!["1 stalled: Could not move to (10,11): robo cannot cross wall.".to_string()]);
+
+  let tick = repl.world.tick;
+  repl.handle_line(":step 5");
+  assert_eq!(repl.world.tick, tick + 5);
+}
@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use crate::model::auto::AutoNdx;
+
+/// Decides which procs run each tick and for how long, so that a few busy scripts can't starve the frame.
+pub struct Scheduler {
+  /// How many procs may run per tick, across all autos.
+  pub budget: usize,
+  /// How many steps a proc may run for on its turn before it has to yield until next tick.
+  pub slice: usize,
+  pub priorities: HashMap<AutoNdx, i32>,
+  pub usage: Vec<u64>,
+  cursor: usize,
+}
+
+impl Scheduler {
+  pub fn new(budget: usize, slice: usize) -> Self {
+    Self {
+      budget,
+      slice,
+      priorities: HashMap::new(),
+      usage: vec![],
+      cursor: 0,
+    }
+  }
+
+  pub fn set_priority(&mut self, auto: AutoNdx, priority: i32) {
+    self.priorities.insert(auto, priority);
+  }
+
+  pub fn priority(&self, auto: AutoNdx) -> i32 {
+    self.priorities.get(&auto).copied().unwrap_or(0)
+  }
+
+  /// Picks which of the running procs get a turn this tick: the accessed auto first, then by priority,
+  /// then round-robin starting after whoever was cut off last time.
+  pub fn pick(&mut self, running: &[AutoNdx], access: AutoNdx, num_procs: usize) -> Vec<AutoNdx> {
+    let num_procs = num_procs.max(1);
+    let cursor = self.cursor % num_procs;
+    let mut order = running.to_vec();
+    order.sort_by_key(|auto| (*auto != access, -self.priority(*auto), (auto.0 + num_procs - cursor) % num_procs));
+    order.truncate(self.budget);
+
+    if order.len() < running.len() {
+      if let Some(last) = order.last() {
+        self.cursor = last.0 + 1;
+      }
+    }
+
+    order
+  }
+
+  /// Records the steps an auto's proc ran for on its turn.
+  pub fn charge(&mut self, auto: AutoNdx, steps: usize) {
+    if self.usage.len() <= auto.0 {
+      self.usage.resize(auto.0 + 1, 0);
+    }
+    self.usage[auto.0] += steps as u64;
+  }
+
+  /// The autos that have run the most steps, busiest first.
+  pub fn top_usage(&self, count: usize) -> Vec<(AutoNdx, u64)> {
+    let mut usage = self.usage.iter().enumerate()
+      .filter(|(_, used)| **used > 0)
+      .map(|(ndx, used)| (AutoNdx(ndx), *used))
+      .collect::<Vec<_>>();
+    usage.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.0.cmp(&b.0.0)));
+    usage.truncate(count);
+    usage
+  }
+}
+
+#[test]
+fn test_scheduler_round_robin() {
+  let mut scheduler = Scheduler::new(2, 100);
+  let running = (0..5).map(AutoNdx).collect::<Vec<_>>();
+
+  assert_eq!(scheduler.pick(&running, AutoNdx(4), 5), vec![AutoNdx(4), AutoNdx(0)]);
+  assert_eq!(scheduler.pick(&running, AutoNdx(4), 5), vec![AutoNdx(4), AutoNdx(1)]);
+  assert_eq!(scheduler.pick(&running, AutoNdx(4), 5), vec![AutoNdx(4), AutoNdx(2)]);
+
+  // priority beats the round-robin, but not the accessed auto
+  scheduler.set_priority(AutoNdx(0), 1);
+  assert_eq!(scheduler.pick(&running, AutoNdx(4), 5), vec![AutoNdx(4), AutoNdx(0)]);
+
+  // everyone fits, so the cursor doesn't move
+  scheduler.budget = 10;
+  assert_eq!(scheduler.pick(&running, AutoNdx(4), 5).len(), 5);
+
+  scheduler.charge(AutoNdx(4), 30);
+  scheduler.charge(AutoNdx(0), 100);
+  scheduler.charge(AutoNdx(0), 20);
+  assert_eq!(scheduler.top_usage(1), vec![(AutoNdx(0), 120)]);
+}
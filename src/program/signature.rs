@@ -0,0 +1,338 @@
+use bevy::prelude::IVec2;
+use conniver::{Val, object::read_string};
+
+use crate::model::{auto::AutoNdx, dir::Dir, kind::Kind, world::World};
+
+/// What a message parameter accepts, and what it is converted to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParamType {
+  /// The index of an existing auto.
+  Auto,
+  Int,
+  /// The name of a defined kind, or `ground` or `any`.
+  Kind,
+  Dir,
+  /// Two ints, `x y`.
+  IVec2,
+  /// A symbol or string.
+  Name,
+  /// One of a fixed set of names, written `a|b|c`.
+  Choice(Vec<String>),
+  Any,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Param {
+  pub name: String,
+  pub param_type: ParamType,
+  pub optional: bool,
+  /// Takes every argument that's left.
+  pub rest: bool,
+}
+
+/// A message's name and parameters, written like `set-item auto:auto loc:ivec2 kind:kind [count:int]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signature {
+  pub name: String,
+  pub params: Vec<Param>,
+}
+
+/// A converted argument.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Arg {
+  Auto(AutoNdx),
+  Int(i32),
+  Kind(Kind),
+  Dir(Dir),
+  IVec2(IVec2),
+  Name(String),
+  Any(Val),
+  Rest(Vec<Val>),
+  Missing,
+}
+
+/// A message's arguments, in the order of its signature's parameters.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Args {
+  /// The message they were sent with, for handlers shared between messages.
+  pub name: String,
+  pub vals: Vec<Arg>,
+}
+
+impl Args {
+  pub fn auto(&self, ndx: usize) -> AutoNdx {
+    self.opt_auto(ndx).unwrap_or_else(|| panic!("expected an auto, got {:?}", self.vals[ndx]))
+  }
+
+  pub fn opt_auto(&self, ndx: usize) -> Option<AutoNdx> {
+    match self.vals.get(ndx) {
+      Some(Arg::Auto(auto)) => Some(*auto),
+      _ => None,
+    }
+  }
+
+  pub fn int(&self, ndx: usize) -> i32 {
+    self.opt_int(ndx).unwrap_or_else(|| panic!("expected an int, got {:?}", self.vals[ndx]))
+  }
+
+  pub fn opt_int(&self, ndx: usize) -> Option<i32> {
+    match self.vals.get(ndx) {
+      Some(Arg::Int(int)) => Some(*int),
+      _ => None,
+    }
+  }
+
+  pub fn kind(&self, ndx: usize) -> Kind {
+    match &self.vals[ndx] {
+      Arg::Kind(kind) => *kind,
+      arg => panic!("expected a kind, got {arg:?}"),
+    }
+  }
+
+  pub fn dir(&self, ndx: usize) -> Dir {
+    match &self.vals[ndx] {
+      Arg::Dir(dir) => *dir,
+      arg => panic!("expected a dir, got {arg:?}"),
+    }
+  }
+
+  pub fn ivec2(&self, ndx: usize) -> IVec2 {
+    match &self.vals[ndx] {
+      Arg::IVec2(loc) => *loc,
+      arg => panic!("expected x y, got {arg:?}"),
+    }
+  }
+
+  pub fn name(&self, ndx: usize) -> &str {
+    self.opt_name(ndx).unwrap_or_else(|| panic!("expected a name, got {:?}", self.vals[ndx]))
+  }
+
+  pub fn opt_name(&self, ndx: usize) -> Option<&str> {
+    match self.vals.get(ndx) {
+      Some(Arg::Name(name)) => Some(name),
+      _ => None,
+    }
+  }
+
+  pub fn val(&self, ndx: usize) -> Val {
+    self.opt_val(ndx).unwrap_or_default()
+  }
+
+  pub fn opt_val(&self, ndx: usize) -> Option<Val> {
+    match self.vals.get(ndx) {
+      Some(Arg::Any(val)) => Some(val.clone()),
+      _ => None,
+    }
+  }
+
+  pub fn rest(&self, ndx: usize) -> &[Val] {
+    match self.vals.get(ndx) {
+      Some(Arg::Rest(rest)) => rest,
+      _ => &[],
+    }
+  }
+}
+
+impl ParamType {
+  fn parse(name: &str) -> ParamType {
+    match name {
+      "auto" => ParamType::Auto,
+      "int" => ParamType::Int,
+      "kind" => ParamType::Kind,
+      "dir" => ParamType::Dir,
+      "ivec2" => ParamType::IVec2,
+      "name" => ParamType::Name,
+      "any" => ParamType::Any,
+      _ if name.contains('|') => ParamType::Choice(name.split('|').map(str::to_string).collect()),
+      _ => panic!("unknown param type {name}"),
+    }
+  }
+
+  pub fn to_str(&self) -> String {
+    match self {
+      ParamType::Auto => "auto".to_string(),
+      ParamType::Int => "int".to_string(),
+      ParamType::Kind => "kind".to_string(),
+      ParamType::Dir => "dir".to_string(),
+      ParamType::IVec2 => "ivec2".to_string(),
+      ParamType::Name => "name".to_string(),
+      ParamType::Choice(choices) => choices.join("|"),
+      ParamType::Any => "any".to_string(),
+    }
+  }
+}
+
+impl Signature {
+  pub fn parse(spec: &str) -> Signature {
+    let mut words = spec.split_whitespace();
+    let name = words.next().expect("signature needs a name").to_string();
+    let params = words.map(|word| {
+      let optional = word.starts_with('[') && word.ends_with(']');
+      let word = word.trim_start_matches('[').trim_end_matches(']');
+      let rest = word.ends_with("...");
+      let word = word.trim_end_matches("...");
+      let (name, param_type) = word.split_once(':').unwrap_or((word, "any"));
+      Param {
+        name: name.to_string(),
+        param_type: ParamType::parse(param_type),
+        optional,
+        rest,
+      }
+    }).collect();
+    Signature { name, params }
+  }
+
+  /// Like `(set-item auto x y kind [count])`.
+  pub fn usage(&self) -> String {
+    let mut words = vec![self.name.clone()];
+    for param in &self.params {
+      let word = match &param.param_type {
+        ParamType::IVec2 => "x y".to_string(),
+        ParamType::Choice(choices) => choices.join("|"),
+        _ => param.name.clone(),
+      };
+      words.push(if param.optional {
+        format!("[{word}]")
+      } else if param.rest {
+        format!("{word}...")
+      } else {
+        word
+      });
+    }
+    format!("({})", words.join(" "))
+  }
+
+  /// Like `(set-item (auto auto) (loc ivec2) (kind kind) (count int optional))`, for `help`.
+  pub fn to_val(&self) -> Val {
+    let mut result = vec![Val::Sym(self.name.clone())];
+    for param in &self.params {
+      let mut entry = vec![Val::Sym(param.name.clone()), Val::Sym(param.param_type.to_str())];
+      if param.optional {
+        entry.push(Val::Sym("optional".to_string()));
+      }
+      if param.rest {
+        entry.push(Val::Sym("rest".to_string()));
+      }
+      result.push(Val::List(entry));
+    }
+    Val::List(result)
+  }
+
+  /// Checks and converts a message's arguments, which start after the message name. Errors are the
+  /// strings handlers return to scripts.
+  pub fn parse_args(&self, message: &[Val], world: &World) -> Result<Args, String> {
+    let usage = || format!("usage: {}", self.usage());
+    let mut vals = message.iter().skip(1);
+    let mut args = vec![];
+    for param in &self.params {
+      if param.rest {
+        args.push(Arg::Rest(vals.by_ref().cloned().collect()));
+        continue;
+      }
+      let val = match vals.next() {
+        Some(val) => val,
+        None if param.optional => {
+          args.push(Arg::Missing);
+          continue;
+        }
+        None => return Err(usage()),
+      };
+      let arg = match &param.param_type {
+        ParamType::Auto => {
+          let ndx = num(val).ok_or_else(usage)? as usize;
+          if ndx >= world.autos.len() {
+            return Err(format!("error: no auto {ndx}"));
+          }
+          Arg::Auto(AutoNdx(ndx))
+        }
+        ParamType::Int => Arg::Int(num(val).ok_or_else(usage)?),
+        ParamType::Kind => {
+          let name = name(val).ok_or_else(usage)?;
+          let kind = world.kinds.get(&name);
+          if kind == world.kinds.missingno() && name != "any" && name != world.kinds.name(kind) {
+            return Err(format!("error: unknown kind {name}"));
+          }
+          Arg::Kind(kind)
+        }
+        ParamType::Dir => {
+          let dir = Dir::from_str(&name(val).ok_or_else(usage)?);
+          if dir == Dir::None {
+            return Err(usage());
+          }
+          Arg::Dir(dir)
+        }
+        ParamType::IVec2 => {
+          let x = num(val).ok_or_else(usage)?;
+          let y = vals.next().and_then(num).ok_or_else(usage)?;
+          Arg::IVec2(IVec2::new(x, y))
+        }
+        ParamType::Name => Arg::Name(name(val).ok_or_else(usage)?),
+        ParamType::Choice(choices) => {
+          let name = name(val).ok_or_else(usage)?;
+          if !choices.contains(&name) {
+            return Err(usage());
+          }
+          Arg::Name(name)
+        }
+        ParamType::Any => Arg::Any(val.clone()),
+      };
+      args.push(arg);
+    }
+    if vals.next().is_some() {
+      return Err(usage());
+    }
+    Ok(Args { name: self.name.clone(), vals: args })
+  }
+}
+
+fn num(val: &Val) -> Option<i32> {
+  if let Val::Num(num) = val {
+    Some(*num as i32)
+  } else {
+    None
+  }
+}
+
+fn name(val: &Val) -> Option<String> {
+  match val {
+    Val::Sym(_) | Val::String(_) => Some(read_string(val)),
+    _ => None,
+  }
+}
+
+#[test]
+fn test_signature() {
+  use conniver::p;
+
+  let world = World::new_test();
+  let signature = Signature::parse("set-item auto:auto loc:ivec2 kind:kind [count:int]");
+  assert_eq!(signature.usage(), "(set-item auto x y kind [count])");
+  assert_eq!(signature.to_val(), p("(set-item (auto auto) (loc ivec2) (kind kind) (count int optional))"));
+
+  let args = signature.parse_args(&[p("set-item"), p("0"), p("3"), p("4"), p("rock")], &world).unwrap();
+  assert_eq!(args.auto(0), AutoNdx(0));
+  assert_eq!(args.ivec2(1), IVec2::new(3, 4));
+  assert_eq!(args.kind(2), world.kinds.get("rock"));
+  assert_eq!(args.opt_int(3), None);
+
+  let bad = |message: &str| {
+    if let Val::List(message) = p(message) {
+      signature.parse_args(&message, &world).unwrap_err()
+    } else {
+      unreachable!()
+    }
+  };
+  assert_eq!(bad("(set-item 0 3)"), "usage: (set-item auto x y kind [count])");
+  assert_eq!(bad("(set-item 0 3 4 rock many)"), "usage: (set-item auto x y kind [count])");
+  assert_eq!(bad("(set-item 0 3 4 rock 2 extra)"), "usage: (set-item auto x y kind [count])");
+  assert_eq!(bad("(set-item 99 3 4 rock)"), "error: no auto 99");
+  assert_eq!(bad("(set-item 0 3 4 unobtainium)"), "error: unknown kind unobtainium");
+
+  let signature = Signature::parse("print line:any...");
+  assert_eq!(signature.usage(), "(print line...)");
+  let args = signature.parse_args(&[p("print"), p("1"), p("2")], &world).unwrap();
+  assert_eq!(args.rest(0), &[p("1"), p("2")]);
+
+  let signature = Signature::parse("grant auto:auto capability:admin|robot");
+  assert_eq!(signature.usage(), "(grant auto admin|robot)");
+}
@@ -1,9 +1,9 @@
 #![cfg(test)]
 
 use bevy::prelude::IVec2;
-use conniver::{val::p_all, p};
+use conniver::{val::p_all, p, Val, object::read_string};
 
-use crate::{model::{world::World, auto::{AutoNdx, Auto, auto_action_finished}, act::Action, kind::Kind, dir::Dir}, program::{program::ProgramSpace}};
+use crate::{model::{world::World, auto::{AutoNdx, Auto, auto_action_finished}, act::Action, kind::Kind, dir::Dir, task::test_task_world}, program::{program::ProgramSpace}};
 
 pub fn run1(world: &mut World, program: &mut ProgramSpace, dur: f64) {
   program.update(dur);
@@ -177,7 +177,7 @@ fn test_input() {
         ((= char 'D) (set-program '(step e)))
         ((= char 'W) (set-program '(step n)))
         ((= char 'S) (set-program '(step s)))
-        ((= char 'E) (if (= \"nothing\" (item-at me 0 0)) 
+        ((= char 'E) (if (= \"nothing\" (car (item-at me 0 0))) 
           (set-program '(pick))
           (set-program '(place))
         ))
@@ -232,7 +232,7 @@ fn test_input_pick_place() {
         ((= char 'D) (set-program '(move e)))
         ((= char 'W) (set-program '(move n)))
         ((= char 'S) (set-program '(move s)))
-        ((= char 'E) (if (= \"nothing\" (item-at me 0 0)) 
+        ((= char 'E) (if (= \"nothing\" (car (item-at me 0 0))) 
           (set-program '(pick))
           (set-program '(place))
         ))
@@ -520,3 +520,760 @@ fn test_automine() {
 //     (set-item earth-auto 10 10 rock)
 //   )"));
 // }
+
+#[test]
+fn test_kind_prop() {
+  let mut world = World::new_blank();
+  let space = AutoNdx(0);
+  let mut program = ProgramSpace::new(space);
+
+  program.interrupt(space, p("(do
+    (define-kind ore
+      (value 3)
+      (description \"raw ore\")
+    )
+    (define ore-value (kind-prop ore value))
+    (define ore-description (kind-prop ore description))
+    (define ore-weight (kind-prop ore weight))
+  )"));
+  run100(&mut world, &mut program, space, -1);
+
+  assert_eq!(program.get_var(space, &"ore-value".to_string()), p("3"));
+  assert_eq!(program.get_var(space, &"ore-description".to_string()), p("\"raw ore\""));
+  assert!(program.get_var(space, &"ore-weight".to_string()).is_nil());
+}
+
+#[test]
+fn test_item_at_fog() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  let rock = world.kinds.get("rock");
+  let red = world.forces.define("red");
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  world.set_item(space, IVec2::new(12, 10), rock);
+  world.set_item(space, IVec2::new(30, 30), rock);
+
+  world.kinds.set_by_val("robo", p("((sight 3))"));
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(10, 10),
+    parent: space,
+    force: red,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+  world.update(0.1);
+
+  let mut program = ProgramSpace::new(robo);
+  program.interrupt(robo, p("(do
+    (define near (item-at 0 12 10))
+    (define far (item-at 0 30 30))
+    (define far-route (route 30 30))
+  )"));
+  run100(&mut world, &mut program, robo, -1);
+
+  assert_eq!(program.get_var(robo, &"near".to_string()), p("(\"rock\" 1)"));
+  assert!(program.get_var(robo, &"far".to_string()).is_nil());
+  assert_eq!(program.get_var(robo, &"far-route".to_string()), p("\"no route\""));
+}
+
+#[test]
+fn test_budget() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  let mut robos = vec![];
+  for x in 0..4 {
+    robos.push(world.create_auto(Auto {
+      kind: world.kinds.get("robo"),
+      loc: IVec2::new(x, 0),
+      parent: space,
+      dim: IVec2::new(1, 1),
+      ..Auto::default()
+    }));
+  }
+
+  let mut program = ProgramSpace::new(robos[0]);
+  program.scheduler.budget = 2;
+  program.scheduler.slice = 50;
+  // the accessed auto spins without ever sending a message
+  program.set_program(robos[0], p("(loop (define spin 1))"));
+  for robo in &robos[1..] {
+    program.set_program(*robo, p("(loop (set-item me 0 0 rock))"));
+  }
+  for _ in 0..12 {
+    run1(&mut world, &mut program, 1.0);
+  }
+
+  // the spinning auto runs every tick but is cut off at its slice, and the rest share what's left
+  let usage = program.scheduler.top_usage(10);
+  assert_eq!(usage[0], (robos[0], 12 * 50));
+  for robo in &robos[1..] {
+    assert!(usage.iter().any(|(auto, used)| auto == robo && *used > 0));
+    assert_eq!(world.get_item(*robo, IVec2::new(0, 0)), world.kinds.get("rock"));
+  }
+}
+
+#[test]
+fn test_error_log() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  let mut program = ProgramSpace::new(space);
+
+  program.interrupt(space, p("(do
+    (define before (last-error))
+    (item-at 0 1)
+    (define after (last-error))
+  )"));
+  run100(&mut world, &mut program, space, -1);
+
+  assert!(program.get_var(space, &"before".to_string()).is_nil());
+  let error = program.last_error(space).unwrap();
+  assert_eq!(error.message, "item-at");
+  assert_eq!(error.args, vec![p("0"), p("1")]);
+  assert_eq!(error.error, "usage: (item-at auto x y)");
+  assert_eq!(program.errors(space).len(), 1);
+  assert_eq!(program.get_var(space, &"after".to_string()), Val::String(error.describe()));
+}
+
+#[test]
+fn test_interpreter_error_log() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  let mut program = ProgramSpace::new(space);
+
+  program.interrupt(space, p("(do
+    (define before 1)
+    (no-such-function 1 2)
+    (define after 1)
+  )"));
+  run100(&mut world, &mut program, space, -1);
+
+  // the interpreter's own errors stop the proc and end up in the log too
+  assert!(program.get_var(space, &"after".to_string()).is_nil());
+  let error = program.last_error(space).unwrap();
+  assert_eq!(error.message, "eval");
+  assert!(!error.error.is_empty());
+  assert_eq!(program.errors(space).len(), 1);
+}
+
+#[test]
+fn test_eval_output() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  let mut program = ProgramSpace::new(space);
+
+  program.eval(space, "(print \"hello \" \"world\")");
+  run100(&mut world, &mut program, space, -1);
+  assert_eq!(program.output.len(), 2);
+  assert_eq!(program.output[0], "hello world");
+
+  program.eval(space, "(item-at 0 10 10)");
+  run100(&mut world, &mut program, space, -1);
+  assert_eq!(program.output.last(), Some(&format!("0> {}", read_string(&p("(\"nothing\" 0)")))));
+
+  // every form runs, and unbalanced input is turned away
+  program.eval(space, "(define a 1) (define b 2)");
+  run100(&mut world, &mut program, space, -1);
+  assert_eq!(program.get_var(space, &"a".to_string()), Val::Num(1.0));
+  assert_eq!(program.get_var(space, &"b".to_string()), Val::Num(2.0));
+  program.eval(space, "(define c (+ 1 2)");
+  run100(&mut world, &mut program, space, -1);
+  assert_eq!(program.output.last(), Some(&"0> error: missing )".to_string()));
+  assert!(program.get_var(space, &"c".to_string()).is_nil());
+}
+
+#[test]
+fn test_mailbox() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  let dispatcher = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(0, 0),
+    parent: space,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+  let worker = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(5, 0),
+    parent: space,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+
+  let mut program = ProgramSpace::new(dispatcher);
+  program.set_program(worker, p("(define job (receive))"));
+  for _ in 0..5 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  // nothing has been sent yet, so the worker is still waiting
+  assert!(!program.idle(worker));
+
+  program.set_program(dispatcher, p(&format!("(send {} '(mine 3 4))", worker.0)));
+  for _ in 0..5 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert!(program.idle(worker));
+  assert_eq!(program.get_var(worker, &"job".to_string()), p(&format!("({} (mine 3 4))", dispatcher.0)));
+
+  program.interrupt(space, p("(do
+    (define (handle from message) (print \"got \" message))
+    (on-message 'handle)
+  )"));
+  run100(&mut world, &mut program, space, -1);
+  program.set_program(worker, p("(send 0 'done)"));
+  for _ in 0..5 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert_eq!(program.output.last(), Some(&"got done".to_string()));
+}
+
+#[test]
+fn test_world_event_interrupts() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  let loc = IVec2::new(10, 10);
+  let rock = world.kinds.get("rock");
+  let machine = world.create_auto(Auto {
+    kind: world.kinds.get("machine"),
+    loc,
+    parent: space,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc,
+    items: vec![rock],
+    parent: space,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+
+  let mut program = ProgramSpace::new(robo);
+  program.interrupt(machine, p("(do
+    (define (item-arrived x y kind) (print \"arrived \" kind))
+    (subscribe 'item-arrived)
+  )"));
+  program.interrupt(robo, p("(do
+    (define (action-finished) (print \"finished\"))
+    (subscribe 'action-finished)
+    (subscribe 'frobnicated)
+  )"));
+  for _ in 0..5 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert_eq!(program.last_error(robo).unwrap().error, "error: unknown event frobnicated");
+
+  program.set_program(robo, p("(place)"));
+  for _ in 0..5 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert!(program.output.contains(&"arrived rock".to_string()));
+  assert!(program.output.contains(&"finished".to_string()));
+}
+
+#[test]
+fn test_timer_sim_time() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  let mut program = ProgramSpace::new(space);
+
+  // four frames to a tick, so a sleep takes as long in simulation time however fast frames come
+  program.interrupt(space, p("(do (sleep 4) (define woke (now)))"));
+  for _ in 0..12 {
+    run1(&mut world, &mut program, 0.25);
+  }
+  assert!(program.get_var(space, &"woke".to_string()).is_nil());
+  for _ in 0..12 {
+    run1(&mut world, &mut program, 0.25);
+  }
+  assert_eq!(program.get_var(space, &"woke".to_string()), Val::Num(4.0));
+}
+
+#[test]
+fn test_timer_messages() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  let mut program = ProgramSpace::new(space);
+
+  program.interrupt(space, p("(do
+    (define start (now))
+    (sleep 10)
+    (define end (now))
+  )"));
+  let mut steps = 0;
+  while !program.idle(space) && steps < 100 {
+    run1(&mut world, &mut program, 1.0);
+    steps += 1;
+  }
+  let start = program.get_var(space, &"start".to_string());
+  let end = program.get_var(space, &"end".to_string());
+  if let (Val::Num(start), Val::Num(end)) = (start, end) {
+    assert!(end - start >= 10.0);
+  } else {
+    panic!("now should return the tick");
+  }
+
+  program.interrupt(space, p("(do
+    (after 3 '(print \"later\"))
+    (define ticker (every 2 '(print \"tick\")))
+  )"));
+  for _ in 0..8 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  let count = |program: &ProgramSpace, line: &str| program.output.iter().filter(|out| *out == line).count();
+  assert_eq!(count(&program, "later"), 1);
+  assert!(count(&program, "tick") >= 3);
+
+  program.interrupt(space, p("(cancel-timer ticker)"));
+  run1(&mut world, &mut program, 1.0);
+  let ticks = count(&program, "tick");
+  for _ in 0..8 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert_eq!(count(&program, "tick"), ticks);
+}
+
+#[test]
+fn test_capabilities() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  let red = world.forces.define("red");
+  let blue = world.forces.define("blue");
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(10, 10),
+    parent: space,
+    force: red,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+  let other = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(11, 10),
+    parent: space,
+    force: blue,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+  let rock = world.kinds.get("rock");
+  let autos = world.autos.len();
+
+  let mut program = ProgramSpace::new(robo);
+  program.interrupt(robo, p(&format!("(do
+    (set-item me 0 0 rock)
+    (set-item {} 0 0 rock)
+    (create-auto (kind robo) (loc 1 1) (parent 0))
+    (define-kind rock (stack 99))
+    (quit)
+  )", other.0)));
+  run100(&mut world, &mut program, robo, -1);
+
+  assert_eq!(world.get_item(robo, IVec2::new(0, 0)), rock);
+  assert_eq!(world.get_item(other, IVec2::new(0, 0)), Kind(0));
+  assert_eq!(world.autos.len(), autos);
+  assert_eq!(world.kinds.get_data(rock).max_stack(), 1);
+  let errors = program.errors(robo).iter().map(|error| error.error.clone()).collect::<Vec<String>>();
+  assert_eq!(errors, vec![
+    format!("error: auto {} is not ours", other.0),
+    "error: create-auto is not permitted for robot".to_string(),
+    "error: define-kind is not permitted for robot".to_string(),
+    "error: quit is not permitted for robot".to_string(),
+  ]);
+
+  // the loader can hand out admin rights
+  program.interrupt(space, p(&format!("(grant {} admin)", robo.0)));
+  run100(&mut world, &mut program, space, -1);
+  program.interrupt(robo, p(&format!("(set-item {} 0 0 rock)", other.0)));
+  run100(&mut world, &mut program, robo, -1);
+  assert_eq!(world.get_item(other, IVec2::new(0, 0)), rock);
+}
+
+#[test]
+fn test_quit() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  let mut program = ProgramSpace::new(space);
+
+  program.interrupt(space, p("(on-quit '(print \"saved\"))"));
+  run100(&mut world, &mut program, space, -1);
+  assert!(!program.ready_to_quit());
+
+  program.interrupt(space, p("(quit)"));
+  run1(&mut world, &mut program, 1.0);
+  assert!(program.quit_requested.is_some());
+  let mut steps = 0;
+  while !program.ready_to_quit() && steps < 10 {
+    run1(&mut world, &mut program, 1.0);
+    steps += 1;
+  }
+  assert!(program.ready_to_quit());
+  assert_eq!(program.output.last(), Some(&"saved".to_string()));
+}
+
+#[test]
+fn test_debugger() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  let start = IVec2::new(10, 10);
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: start,
+    parent: space,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+
+  let mut program = ProgramSpace::new(space);
+  program.interrupt(space, p(&format!("(debug {} 'break 'step)", robo.0)));
+  run100(&mut world, &mut program, space, -1);
+  assert_eq!(program.debug_command(robo, "breaks", None), Ok("step".to_string()));
+
+  program.set_program(robo, p("(do
+    (define a \"one\")
+    (step n)
+    (define b \"two\")
+    (step n)
+  )"));
+  for _ in 0..10 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert_eq!(world.get_auto(robo).loc, start);
+  assert_eq!(program.debug_command(robo, "where", None), Ok("paused at (step n)".to_string()));
+  assert_eq!(program.debug_command(robo, "var", Some("a")), Ok("one".to_string()));
+
+  // a step finishes the current message and stops at the next one
+  program.debug_command(robo, "step", None).unwrap();
+  for _ in 0..10 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert_eq!(world.get_auto(robo).loc, start + IVec2::new(0, 1));
+  assert_eq!(program.debug_command(robo, "where", None), Ok("paused at (step n)".to_string()));
+  assert_eq!(program.debug_command(robo, "var", Some("b")), Ok("two".to_string()));
+
+  program.debug_command(robo, "unbreak", Some("step")).unwrap();
+  program.debug_command(robo, "resume", None).unwrap();
+  for _ in 0..10 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert_eq!(world.get_auto(robo).loc, start + IVec2::new(0, 2));
+  assert!(program.debug_command(robo, "frobnicate", None).is_err());
+}
+
+#[test]
+fn test_help() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  let mut program = ProgramSpace::new(space);
+
+  program.interrupt(space, p("(do
+    (define all (help))
+    (define one (help 'set-item))
+    (step north)
+  )"));
+  run100(&mut world, &mut program, space, -1);
+
+  assert_eq!(program.get_var(space, &"one".to_string()), Val::String("(set-item auto x y kind [count])".to_string()));
+  if let Val::List(all) = program.get_var(space, &"all".to_string()) {
+    assert!(all.contains(&p("(item-at (auto auto) (loc ivec2))")));
+    assert_eq!(all.len(), program.signatures().len());
+  } else {
+    panic!("help should list every message");
+  }
+  assert_eq!(program.last_error(space).unwrap().error, "usage: (step dir)");
+}
+
+#[test]
+fn test_set_program() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  let start = IVec2::new(10, 10);
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: start,
+    parent: space,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+
+  let mut program = ProgramSpace::new(space);
+  program.interrupt(space, p(&format!("(set-program '(step n) {} once)", robo.0)));
+  for _ in 0..10 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert_eq!(world.get_auto(robo).loc, start + IVec2::new(0, 1));
+  assert!(world.get_auto(robo).program.is_nil());
+
+  // an override keeps restarting whenever the auto is idle
+  program.interrupt(space, p(&format!("(set-program '(step n) {} always)", robo.0)));
+  for _ in 0..10 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert!(world.get_auto(robo).loc.y > start.y + 2);
+
+  program.interrupt(robo, p("(define mine (get-program))"));
+  for _ in 0..5 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert_eq!(program.get_var(robo, &"mine".to_string()), p("(step n)"));
+
+  program.interrupt(robo, p("(clear-program)"));
+  for _ in 0..10 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  let loc = world.get_auto(robo).loc;
+  for _ in 0..10 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert_eq!(world.get_auto(robo).loc, loc);
+}
+
+#[test]
+fn test_require() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  let dir = std::env::temp_dir().join(format!("rs98-modules-{}", std::process::id()));
+  std::fs::create_dir_all(&dir).unwrap();
+  std::fs::write(dir.join("mathlib.cnvr"), "
+    (provide double)
+    (define factor 2)
+    (define (double x) (+ x x))
+    (print \"loaded\")
+  ").unwrap();
+
+  let mut program = ProgramSpace::new(space);
+  program.modules.paths = vec![dir.to_string_lossy().to_string()];
+  program.interrupt(space, p("(do
+    (require \"mathlib\")
+    (require \"mathlib\")
+    (require \"mathlib\" m)
+    (define a (double 3))
+    (define b (m/double 4))
+    (define missing (require \"nope\"))
+    (define forged (module-loaded \"other\"))
+  )"));
+  run100(&mut world, &mut program, space, -1);
+  std::fs::remove_dir_all(&dir).ok();
+
+  // loaded once, with its own names kept apart
+  assert_eq!(program.output.iter().filter(|line| *line == "loaded").count(), 1);
+  assert_eq!(program.get_var(space, &"a".to_string()), Val::Num(6.0));
+  assert_eq!(program.get_var(space, &"b".to_string()), Val::Num(8.0));
+  assert_eq!(program.get_var(space, &"mathlib/factor".to_string()), Val::Num(2.0));
+  assert!(matches!(program.get_var(space, &"missing".to_string()), Val::String(error) if error.starts_with("error: no module nope")));
+  assert_eq!(program.get_var(space, &"forged".to_string()), Val::String("error: module-loaded is only for require".to_string()));
+}
+
+#[test]
+fn test_behaviour_tree() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  world.set_tile(space, IVec2::new(12, 13), world.kinds.get("wall"));
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(10, 10),
+    parent: space,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+
+  let mut program = ProgramSpace::new(space);
+  program.interrupt(space, p(&format!("(do
+    (define-behaviour walk '(sequence (step n) (selector (condition (= 1 2)) (step e))))
+    (set-program '(behaviour walk) {})
+  )", robo.0)));
+  for _ in 0..200 {
+    run1(&mut world, &mut program, 1.0);
+  }
+
+  // two passes, then the wall stalls the first leaf and fails every pass after
+  assert_eq!(world.get_auto(robo).loc, IVec2::new(12, 12));
+  let status = program.behaviour_status(robo).unwrap();
+  assert!(status.ends_with("last failed"), "{status}");
+}
+
+#[test]
+fn test_work_task() {
+  let (mut world, table, robo) = test_task_world();
+  let space = AutoNdx(0);
+  let rock = world.kinds.get("rock");
+  world.set_item(space, IVec2::new(3, 1), rock);
+
+  let mut program = ProgramSpace::new(space);
+  program.interrupt(space, p(&format!("(define bad (post-task supply {} 2 0 rock))", table.0)));
+  run1(&mut world, &mut program, 1.0);
+  assert_eq!(program.get_var(space, &"bad".to_string()), Val::String(format!("error: auto {} has no slot 2 0", table.0)));
+  assert!(world.tasks.tasks.is_empty());
+
+  program.interrupt(space, p(&format!("(post-task supply {} 0 0 rock)", table.0)));
+  program.interrupt(robo, p("(define done (work-task))"));
+  for _ in 0..200 {
+    run1(&mut world, &mut program, 1.0);
+  }
+
+  assert_eq!(program.get_var(robo, &"done".to_string()), Val::Num(1.0));
+  assert_eq!(world.get_item(table, IVec2::new(0, 0)), rock);
+  assert!(world.tasks.tasks.is_empty());
+}
+
+#[test]
+fn test_inserter() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  let rock = world.kinds.get("rock");
+  let machine = world.create_auto(Auto {
+    kind: world.kinds.get("machine"),
+    loc: IVec2::new(11, 10),
+    parent: space,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+  world.set_item(space, IVec2::new(9, 10), rock);
+
+  // a filter naming a kind that doesn't exist is refused, like set-filter's
+  let mut program = ProgramSpace::new(space);
+  let autos = world.autos.len();
+  program.interrupt(space, p("(define bad (create-auto (kind inserter) (loc 10 10) (parent 0) (filter rok)))"));
+  run1(&mut world, &mut program, 1.0);
+  assert_eq!(program.get_var(space, &"bad".to_string()), Val::String("error: unknown kind rok".to_string()));
+  assert_eq!(world.autos.len(), autos);
+
+  // the inserter's own program moves only what passes its filter
+  let arm = AutoNdx(autos);
+  program.interrupt(space, p("(create-auto (kind inserter) (loc 10 10) (parent 0) (facing e) (filter widget))"));
+  for _ in 0..5 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert_eq!(world.get_auto(arm).facing, Dir::East);
+  assert_eq!(world.stall_message(arm), Some("Nothing for inserter to take.".to_string()));
+  assert_eq!(world.get_item(space, IVec2::new(9, 10)), rock);
+
+  program.interrupt(space, p(&format!("(set-filter {} rock)", arm.0)));
+  for _ in 0..5 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert_eq!(world.get_item(space, IVec2::new(9, 10)), Kind(0));
+  assert_eq!(world.get_item(machine, IVec2::new(0, 0)), rock);
+}
+
+#[test]
+fn test_rotate_shipped_kinds() {
+  let mut world = World::new_blank();
+  let space = AutoNdx(0);
+  let mut program = ProgramSpace::new(space);
+  program.interrupt(space, p("(do
+    (load \"assets/cnvr/kinds.cnvr\")
+    (define ground (create-auto (kind earth) (loc 0 0) (parent 0) (dim 10 10) (tile lab-tile)))
+    (define machine (create-auto (kind autoprocessor) (loc 2 2) (parent ground)))
+    (define bad (create-auto (kind autoprocessor) (loc 5 5) (parent ground) (facing up)))
+  )"));
+  run100(&mut world, &mut program, space, -1);
+  assert_eq!(program.get_var(space, &"bad".to_string()), Val::String("error: unknown direction up".to_string()));
+  let (ground, machine) = match (program.get_var(space, &"ground".to_string()), program.get_var(space, &"machine".to_string())) {
+    (Val::Num(ground), Val::Num(machine)) => (AutoNdx(ground as usize), AutoNdx(machine as usize)),
+    vars => panic!("expected autos, got {vars:?}"),
+  };
+
+  // a machine with no traction of its own still turns on the floor it stands on, but not into a wall
+  world.set_auto_action(machine, Action::Rotate(Dir::East));
+  world.update(2.0);
+  assert_eq!(world.stall_message(machine), None);
+  assert_eq!(world.get_auto(machine).facing, Dir::East);
+  world.set_tile(ground, IVec2::new(3, 2), world.kinds.get("lab-wall"));
+  world.set_auto_action(machine, Action::Rotate(Dir::North));
+  world.update(2.0);
+  assert_eq!(world.stall_message(machine), Some("Could not turn: lab-wall is in the way at (3,2).".to_string()));
+  assert_eq!(world.get_auto(machine).facing, Dir::East);
+}
+
+#[test]
+fn test_peek_others() {
+  let mut world = World::new_test();
+  world.kinds.set_by_val("robo", p("((sight 3))"));
+  let space = AutoNdx(0);
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  let red = world.forces.define("red");
+  let blue = world.forces.define("blue");
+  let spy = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(1, 1),
+    parent: space,
+    force: red,
+    ..Auto::default()
+  });
+  let other = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(15, 15),
+    parent: space,
+    force: blue,
+    facing: Dir::East,
+    ..Auto::default()
+  });
+
+  // another force's program and behaviour are its own, and it can't be watched turning out of sight
+  let mut program = ProgramSpace::new(space);
+  program.interrupt(spy, p(&format!("(do
+    (define prog (get-program {0}))
+    (define status (behaviour-status {0}))
+    (define dir (facing {0})))", other.0)));
+  for _ in 0..5 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  let not_ours = Val::String(format!("error: auto {} is not ours", other.0));
+  assert_eq!(program.get_var(spy, &"prog".to_string()), not_ours);
+  assert_eq!(program.get_var(spy, &"status".to_string()), not_ours);
+  assert_eq!(program.get_var(spy, &"dir".to_string()), Val::nil());
+
+  // once it's in sight, which way it faces is plain to see
+  world.get_auto_mut(other).loc = IVec2::new(2, 2);
+  program.interrupt(spy, p(&format!("(define dir (facing {}))", other.0)));
+  for _ in 0..5 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert_eq!(program.get_var(spy, &"dir".to_string()), Val::Sym("e".to_string()));
+}
+
+#[test]
+fn test_behaviour_redefine() {
+  let mut world = World::new_test();
+  let space = AutoNdx(0);
+  world.set_all_tiles(space, world.kinds.get("grass"));
+  let robo = world.create_auto(Auto {
+    kind: world.kinds.get("robo"),
+    loc: IVec2::new(10, 10),
+    parent: space,
+    dim: IVec2::new(1, 1),
+    ..Auto::default()
+  });
+
+  // started before its tree is defined
+  let mut program = ProgramSpace::new(space);
+  program.interrupt(space, p(&format!("(set-program '(behaviour haul) {})", robo.0)));
+  for _ in 0..5 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert!(program.behaviour_status(robo).unwrap().starts_with("error"));
+
+  program.interrupt(space, p("(define-behaviour haul '(step n))"));
+  for _ in 0..10 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  let loc = world.get_auto(robo).loc;
+  assert!(loc.y > 10);
+
+  // redefining it reaches the auto already running it
+  program.interrupt(space, p("(define-behaviour haul '(step e))"));
+  for _ in 0..10 {
+    run1(&mut world, &mut program, 1.0);
+  }
+  assert!(world.get_auto(robo).loc.x > loc.x);
+}
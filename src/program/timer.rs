@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use conniver::Val;
+
+use crate::model::auto::AutoNdx;
+
+pub struct Timer {
+  pub id: u64,
+  pub auto: AutoNdx,
+  pub due: u64,
+  /// Rescheduled this many ticks later each time it fires, for `every`.
+  pub every: Option<u64>,
+  pub expr: Val,
+}
+
+/// Ticks that scripts are waiting for, either asleep or with something to run later.
+#[derive(Default)]
+pub struct Timers {
+  timers: Vec<Timer>,
+  sleepers: HashMap<AutoNdx, u64>,
+  next_id: u64,
+}
+
+impl Timers {
+  pub fn add(&mut self, auto: AutoNdx, due: u64, every: Option<u64>, expr: Val) -> u64 {
+    self.next_id += 1;
+    self.timers.push(Timer {
+      id: self.next_id,
+      auto,
+      due,
+      every,
+      expr,
+    });
+    self.next_id
+  }
+
+  /// Only the auto that started a timer may cancel it.
+  pub fn cancel(&mut self, auto: AutoNdx, id: u64) -> bool {
+    let len = self.timers.len();
+    self.timers.retain(|timer| timer.id != id || timer.auto != auto);
+    self.timers.len() < len
+  }
+
+  /// Takes what should run by this tick, rescheduling repeating timers.
+  pub fn due(&mut self, tick: u64) -> Vec<(AutoNdx, Val)> {
+    let mut result = vec![];
+    for timer in &mut self.timers {
+      if timer.due <= tick {
+        result.push((timer.auto, timer.expr.clone()));
+        if let Some(every) = timer.every {
+          timer.due = tick + every.max(1);
+        }
+      }
+    }
+    self.timers.retain(|timer| timer.due > tick);
+    result
+  }
+
+  /// Whether a sleeping auto is done, starting the sleep if it hasn't been yet.
+  pub fn sleep(&mut self, auto: AutoNdx, tick: u64, ticks: u64) -> bool {
+    let wake = *self.sleepers.entry(auto).or_insert(tick + ticks);
+    if tick >= wake {
+      self.sleepers.remove(&auto);
+      true
+    } else {
+      false
+    }
+  }
+
+  /// Forgets a sleep that was abandoned, as when the auto's program is replaced, so its next sleep starts
+  /// fresh.
+  pub fn wake(&mut self, auto: AutoNdx) {
+    self.sleepers.remove(&auto);
+  }
+
+  #[cfg(test)]
+  pub fn pending(&self, auto: AutoNdx) -> usize {
+    self.timers.iter().filter(|timer| timer.auto == auto).count()
+  }
+}
+
+#[test]
+fn test_timers() {
+  let mut timers = Timers::default();
+  let auto = AutoNdx(1);
+  let once = timers.add(auto, 5, None, Val::Sym("once".to_string()));
+  timers.add(auto, 3, Some(3), Val::Sym("every".to_string()));
+
+  assert!(timers.due(2).is_empty());
+  assert_eq!(timers.due(3), vec![(auto, Val::Sym("every".to_string()))]);
+  assert_eq!(timers.due(6).len(), 2);
+  assert_eq!(timers.pending(auto), 1);
+  assert!(!timers.cancel(auto, once));
+  assert!(!timers.cancel(AutoNdx(2), 2));
+  assert!(timers.cancel(auto, 2));
+  assert_eq!(timers.pending(auto), 0);
+
+  assert!(!timers.sleep(auto, 10, 2));
+  assert!(!timers.sleep(auto, 11, 2));
+  assert!(timers.sleep(auto, 12, 2));
+
+  // an abandoned sleep doesn't cut the next one short
+  assert!(!timers.sleep(auto, 20, 2));
+  timers.wake(auto);
+  assert!(!timers.sleep(auto, 21, 5));
+  assert!(!timers.sleep(auto, 22, 5));
+  assert!(timers.sleep(auto, 26, 5));
+}